@@ -0,0 +1,267 @@
+//! Binary checkpoints of a running simulation, to resume it after an interruption.
+//!
+//! All numbers are little-endian:
+//!
+//! | field     | type                                                             |
+//! |-----------|------------------------------------------------------------------|
+//! | magic     | `b"FLUXCKPT"`                                                    |
+//! | version   | `u32`                                                            |
+//! | universe  | a whole [snapshot](crate::snapshot)                              |
+//! | particles | graviton then sub-graviton parameters, see below                 |
+//! | step      | `u32`                                                            |
+//! | dynamics  | `u8` set to `1` if dynamic, then `steps` `u32`, `strength` `f64` |
+//! | emission  | `u8` directions, `u8` set to `1` if seeded then the seed `u64`   |
+//! | bodies    | `u32` count, then each body, see below                           |
+//! | gravitons | `u64` count, then each graviton, see below                       |
+//!
+//! Parameters are `step_size` as `f64`, `quantity` and `life_span` as `u32`. A body is its
+//! position, velocity (2 × `f64` each) and value (`f64`), then its channel as `u32`. A
+//! graviton is its position, speed and value the same way, then its channel and age as
+//! `u32`, and a `u8` set to `1` if a body emitted it, followed by the body's index as
+//! `u32`.
+//!
+//! Directions are `0` for even, `1` for the golden angle, `2` for Halton and `3` for
+//! random.
+
+use crate::{
+    dynamics::Dynamics,
+    gravitons::{Directions, Emission},
+    simulation::Simulation,
+    snapshot::{
+        invalid_data, read_f64, read_parameters, read_point, read_u32, read_universe, write_f64,
+        write_parameters, write_point, write_u32, write_universe,
+    },
+    types::{Body, Particle},
+};
+
+use std::{
+    fs::{self, File},
+    io::{self, BufReader, BufWriter, Read, Write},
+    path::Path,
+};
+
+const MAGIC: &[u8; 8] = b"FLUXCKPT";
+const VERSION: u32 = 2;
+
+/// Saves the simulation, replacing any previous checkpoint at `path` only once complete.
+pub fn save_checkpoint(simulation: &Simulation, path: impl AsRef<Path>) -> io::Result<()> {
+    let path = path.as_ref();
+    let partial = path.with_extension("partial");
+    let mut writer = BufWriter::new(File::create(&partial)?);
+    write_checkpoint(&mut writer, simulation)?;
+    writer.into_inner()?.sync_all()?;
+    fs::rename(partial, path)
+}
+
+pub fn load_checkpoint(path: impl AsRef<Path>) -> io::Result<Simulation> {
+    let mut reader = BufReader::new(File::open(path)?);
+    read_checkpoint(&mut reader)
+}
+
+pub fn write_checkpoint(writer: &mut impl Write, simulation: &Simulation) -> io::Result<()> {
+    writer.write_all(MAGIC)?;
+    write_u32(writer, VERSION)?;
+    write_universe(writer, &simulation.universe)?;
+    write_parameters(writer, &simulation.graviton)?;
+    write_parameters(writer, &simulation.sub_graviton)?;
+    write_u32(writer, simulation.step_index())?;
+
+    match simulation.dynamics() {
+        Some(Dynamics { steps, strength }) => {
+            writer.write_all(&[1])?;
+            write_u32(writer, steps)?;
+            write_f64(writer, strength)?;
+        }
+        None => writer.write_all(&[0])?,
+    }
+    let Emission { directions, seed } = simulation.emission();
+    writer.write_all(&[match directions {
+        Directions::Even => 0,
+        Directions::GoldenAngle => 1,
+        Directions::Halton => 2,
+        Directions::Random => 3,
+    }])?;
+    match seed {
+        Some(seed) => {
+            writer.write_all(&[1])?;
+            writer.write_all(&seed.to_le_bytes())?;
+        }
+        None => writer.write_all(&[0])?,
+    }
+
+    write_u32(writer, simulation.bodies().len() as u32)?;
+    for body in simulation.bodies() {
+        write_point(writer, body.position)?;
+        write_point(writer, body.velocity)?;
+        write_f64(writer, body.value)?;
+        write_u32(writer, body.channel as u32)?;
+    }
+
+    writer.write_all(&(simulation.gravitons().len() as u64).to_le_bytes())?;
+    for graviton in simulation.gravitons() {
+        write_point(writer, graviton.position)?;
+        write_point(writer, graviton.speed)?;
+        write_f64(writer, graviton.value)?;
+        write_u32(writer, graviton.channel as u32)?;
+        write_u32(writer, graviton.age)?;
+        match graviton.body {
+            Some(body) => {
+                writer.write_all(&[1])?;
+                write_u32(writer, body as u32)?;
+            }
+            None => writer.write_all(&[0])?,
+        }
+    }
+    Ok(())
+}
+
+pub fn read_checkpoint(reader: &mut impl Read) -> io::Result<Simulation> {
+    let mut magic = [0; 8];
+    reader.read_exact(&mut magic)?;
+    if &magic != MAGIC {
+        return Err(invalid_data("not a simulation checkpoint"));
+    }
+    let version = read_u32(reader)?;
+    if version != VERSION {
+        return Err(invalid_data(format!(
+            "unsupported checkpoint version {version}"
+        )));
+    }
+    let universe = read_universe(reader)?;
+    let channels = universe.channels().len();
+    let channel = |reader: &mut _| match read_u32(reader)? as usize {
+        channel if channel < channels => Ok(channel),
+        channel => Err(invalid_data(format!("unknown channel {channel}"))),
+    };
+    let parameters = (read_parameters(reader)?, read_parameters(reader)?);
+    let step = read_u32(reader)?;
+
+    let mut dynamic = [0];
+    reader.read_exact(&mut dynamic)?;
+    let dynamics = match dynamic[0] {
+        0 => None,
+        _ => Some(Dynamics {
+            steps: read_u32(reader)?,
+            strength: read_f64(reader)?,
+        }),
+    };
+    let mut emission = Emission::default();
+    let mut directions = [0];
+    reader.read_exact(&mut directions)?;
+    emission.directions = match directions[0] {
+        0 => Directions::Even,
+        1 => Directions::GoldenAngle,
+        2 => Directions::Halton,
+        3 => Directions::Random,
+        other => return Err(invalid_data(format!("unknown directions {other}"))),
+    };
+    let mut seeded = [0];
+    reader.read_exact(&mut seeded)?;
+    if seeded[0] != 0 {
+        let mut seed = [0; 8];
+        reader.read_exact(&mut seed)?;
+        emission.seed = Some(u64::from_le_bytes(seed));
+    }
+
+    let bodies: Vec<_> = (0..read_u32(reader)?)
+        .map(|_| {
+            Ok(Body {
+                position: read_point(reader)?,
+                velocity: read_point(reader)?,
+                value: read_f64(reader)?,
+                channel: channel(reader)?,
+            })
+        })
+        .collect::<io::Result<_>>()?;
+
+    let mut count = [0; 8];
+    reader.read_exact(&mut count)?;
+    let gravitons = (0..u64::from_le_bytes(count))
+        .map(|_| {
+            Ok(Particle {
+                position: read_point(reader)?,
+                speed: read_point(reader)?,
+                value: read_f64(reader)?,
+                channel: channel(reader)?,
+                age: read_u32(reader)?,
+                body: {
+                    let mut emitted = [0];
+                    reader.read_exact(&mut emitted)?;
+                    match emitted[0] {
+                        0 => None,
+                        _ => match read_u32(reader)? as usize {
+                            body if body < bodies.len() => Some(body),
+                            body => return Err(invalid_data(format!("unknown body {body}"))),
+                        },
+                    }
+                },
+            })
+        })
+        .collect::<io::Result<_>>()?;
+
+    Ok(Simulation::restore(
+        universe, parameters, gravitons, bodies, dynamics, emission, step,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        gravitons::ParticleParameters,
+        types::{Channel, Point, Universe},
+    };
+
+    fn bytes(simulation: &Simulation) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        write_checkpoint(&mut bytes, simulation).unwrap();
+        bytes
+    }
+
+    #[test]
+    fn resumes_where_it_left_off() {
+        let mut universe = Universe::new(30, 30, vec![Channel::mass(), Channel::charge()]);
+        universe[(5, 5)].element_mut().unwrap().properties[1].value = -1.0;
+        let body = |x, channel| Body {
+            position: Point { x, y: 15.0 },
+            velocity: Point { x: 0.0, y: 0.25 },
+            value: 1.0,
+            channel,
+        };
+        let mut simulation = Simulation::dynamic(
+            universe,
+            ParticleParameters::new(1.0, 16, 10),
+            ParticleParameters::new(0.9, 8, 5),
+            vec![body(10.0, 0), body(20.0, 1)],
+            Dynamics {
+                steps: 8,
+                strength: 20.0,
+            },
+        )
+        .with_emission(Emission {
+            directions: Directions::Random,
+            seed: Some(7),
+        });
+        for _ in 0..3 {
+            simulation.step();
+        }
+        let written = bytes(&simulation);
+        let mut resumed = read_checkpoint(&mut &written[..]).unwrap();
+        assert_eq!(bytes(&resumed), written);
+        assert_eq!(resumed.step_index(), 3);
+        assert_eq!(resumed.bodies(), simulation.bodies());
+        assert!(resumed.gravitons().iter().any(|g| g.body == Some(1)));
+
+        while simulation.step() {}
+        while resumed.step() {}
+        assert_eq!(bytes(&resumed), bytes(&simulation));
+    }
+
+    #[test]
+    fn rejects_other_files() {
+        let mut snapshot = Vec::new();
+        write_universe(&mut snapshot, &Universe::new(4, 4, vec![Channel::mass()])).unwrap();
+        let error = read_checkpoint(&mut &snapshot[..]).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+}
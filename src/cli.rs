@@ -0,0 +1,507 @@
+//! Command-line parsing.
+
+use simulador_de_fluxo::{
+    gravitons::Directions,
+    numpy::ArrayFormat,
+    poisson::SolverParameters,
+    render::{Colouring, FrameScale, Normalization, SourceBlend},
+    video::VideoFormat,
+    vtk::VtkFormat,
+};
+
+use colorgrad::Color;
+
+use std::{
+    error::Error,
+    fmt::{Display, Formatter, Result as FmtResult},
+    path::PathBuf,
+    str::FromStr,
+};
+
+pub const USAGE: &str = "\
+Usage:
+  simulador_de_fluxo run <scene> [options]
+      Simulate a scene, rendering one frame per step and joining them into a video.
+      Frames of every channel but the first go in subfolders named after them.
+      Takes the rendering options below too.
+      --output <dir>        Folder receiving the frames (default: from the scene)
+      --frame-rate <fps>    Video frame rate (default: from the scene)
+      --steps <n>           Number of graviton steps (default: from the scene)
+      --sub-steps <n>       Number of sub-graviton steps (default: from the scene)
+      --no-video            Don't join the frames into a video
+      --video-format <format>
+                            `mp4` through ffmpeg, `gif` or `apng` without it, or `pipe`,
+                            an mp4 with the frames streamed to ffmpeg instead of saved
+                            (default: mp4)
+      --frame-scale <mode>  Frames the colour range is fitted to: `each` its own, `running`
+                            all so far (restarting from a resumed checkpoint), `final` the
+                            last, or `frame:<n>`, the last two simulated ahead (default: each)
+      --embed-scale         Write the range and curve of each frame in its PNG metadata
+      --seed <n>            Turn the emitted angles randomly, or draw random ones, from
+                            this seed (default: from the scene)
+      --directions <set>    Angles emitted, `even`, `golden`, `halton`, or `random` for
+                            each particle its own (default: from the scene)
+      --checkpoint-every <n>
+                            Save the simulation state every n steps, to resume it later
+      --resume              Continue from the checkpoint in the output folder, if any,
+                            ignoring the scene's universe and particle settings
+      --export <format>     Save the final raw fields as NumPy arrays, `npy` or `npz`,
+                            in the `arrays` subfolder
+      --export-frames       With `--export`, save the arrays of every frame too
+
+  simulador_de_fluxo render <snapshot> [options]
+      Re-render the fields saved by a previous run.
+      Takes the rendering options below too.
+      --output <file>       Image to write (default: the snapshot path with `.png`)
+      --layer <channel>     Channel to render (default: the first one)
+
+  simulador_de_fluxo export <snapshot> [options]
+      Save the raw fields of a snapshot as NumPy arrays.
+      --format <format>     `npy` for a folder of arrays, `npz` for an archive (default)
+      --output <path>       Where to write them (default: next to the snapshot)
+
+  simulador_de_fluxo vtk <snapshot> [options]
+      Save the fields and portals of a snapshot as VTK files, for ParaView.
+      --format <format>     `xml` for `.vti` and `.vtp` files (default), `legacy` for `.vtk`
+      --output <path>       Path of the fields, without extension; the portals go next to
+                            it, suffixed with `_portals` (default: the snapshot path)
+
+  simulador_de_fluxo reference <scene> [options]
+      Compute the exact field of the scene's sources, to check a run's against.
+      --depth <n>           Portal traversals followed (default: 2)
+      --output <file>       Snapshot to write (default: `reference.snap` in the scene's output)
+      --compare <snapshot>  Print the error of a run's fields against the reference
+
+  simulador_de_fluxo solve <scene> [options]
+      Compute the fields from their potential, solving Poisson's equation on the grid.
+      --tolerance <x>       Residual to reach, relative to the sources (default: 1e-6)
+      --max-cycles <n>      Bound on multigrid cycles (default: 100)
+      --output <file>       Snapshot to write (default: `poisson.snap` in the scene's output)
+
+  simulador_de_fluxo inspect <scene>
+      Print the scene's geometry and the amount of particles it will spawn.
+
+  simulador_de_fluxo help
+      Print this message.
+
+Rendering options:
+      --colormap <spec>     Colour gradient for the field: a preset's name, a `.ggr` file,
+                            or a list of colours like `navy, 20%, gold, white`
+                            (default: viridis)
+      --colouring <mode>    What the colours show of the field, `magnitude` through the
+                            colormap or `direction` as hue (default: magnitude)
+      --range <range>       Magnitudes spanning the colours, clipped outside: `max` from 0
+                            to the largest, `percentile:<low>,<high>` like `percentile:1,99`,
+                            or `fixed:<min>,<max>` (default: max)
+      --curve <curve>       Spread of the magnitudes over the colours: `linear`,
+                            `log[:<decades>]` (4 decades by default), or `gamma:<exponent>`
+                            (default: linear)
+      --streamlines <px>    Draw field lines, seeded on a grid of this spacing
+      --quiver <px>         Draw arrows of the field, on a grid of this spacing
+      --line-width <px>     Thickness of the field lines and arrows (default: 1)
+      --no-antialiasing     Draw the field lines and arrows without smoothing their edges
+      --portal-colour <colour>
+                            Colour of the portals, named or in CSS notation; repeated, the
+                            colours of successive pairs
+      --portal-width <px>   Thickness of the portals (default: 3)
+      --no-portal-markers   Don't mark the `point_a` end of the portals, nor the side motion
+                            crossing them comes out of
+      --sources <blend>     Draw the sources: `alone` without the field, `contour` their
+                            outline, `tint` multiplying the field, or `alpha` over it
+      --source-colour <colour>
+                            Colour of the sources (default: white)";
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    Run(RunArgs),
+    Render(RenderArgs),
+    Export(ExportArgs),
+    Vtk(VtkArgs),
+    Reference(ReferenceArgs),
+    Solve(SolveArgs),
+    Inspect { scene: PathBuf },
+    Help,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RunArgs {
+    pub scene: PathBuf,
+    pub output: Option<PathBuf>,
+    pub frame_rate: Option<u32>,
+    pub steps: Option<u32>,
+    pub sub_steps: Option<u32>,
+    pub style: StyleArgs,
+    pub video: bool,
+    pub video_format: VideoFormat,
+    pub frame_scale: FrameScale,
+    pub embed_scale: bool,
+    /// Seed of a random emission, replacing the scene's.
+    pub seed: Option<u64>,
+    pub directions: Option<Directions>,
+    pub checkpoint_every: Option<u32>,
+    pub resume: bool,
+    pub export: Option<ArrayFormat>,
+    pub export_frames: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RenderArgs {
+    pub snapshot: PathBuf,
+    pub output: Option<PathBuf>,
+    pub style: StyleArgs,
+    /// Name of the channel to render, the first one if `None`.
+    pub layer: Option<String>,
+}
+
+/// How fields are turned into images, shared by `run` and `render`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StyleArgs {
+    pub colormap: String,
+    pub colouring: Colouring,
+    pub normalization: Normalization,
+    /// Spacing of the field lines' seeds, none drawn if `None`.
+    pub streamlines: Option<u32>,
+    /// Spacing of the field's arrows, none drawn if `None`.
+    pub quiver: Option<u32>,
+    pub line_width: Option<f64>,
+    pub antialiasing: bool,
+    /// Colours of the pairs of portals in turn, the default ones if empty.
+    pub portal_colours: Vec<Color>,
+    pub portal_width: Option<f64>,
+    pub portal_markers: bool,
+    /// How the sources are drawn, not at all if `None`.
+    pub sources: Option<SourceBlend>,
+    pub source_colour: Option<Color>,
+}
+
+impl Default for StyleArgs {
+    fn default() -> Self {
+        StyleArgs {
+            colormap: DEFAULT_COLORMAP.to_string(),
+            colouring: Colouring::default(),
+            normalization: Normalization::default(),
+            streamlines: None,
+            quiver: None,
+            line_width: None,
+            antialiasing: true,
+            portal_colours: Vec::new(),
+            portal_width: None,
+            portal_markers: true,
+            sources: None,
+            source_colour: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExportArgs {
+    pub snapshot: PathBuf,
+    pub format: ArrayFormat,
+    pub output: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct VtkArgs {
+    pub snapshot: PathBuf,
+    pub format: VtkFormat,
+    pub output: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReferenceArgs {
+    pub scene: PathBuf,
+    pub depth: u32,
+    pub output: Option<PathBuf>,
+    pub compare: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SolveArgs {
+    pub scene: PathBuf,
+    pub parameters: SolverParameters,
+    pub output: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CliError(String);
+
+impl Display for CliError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        f.write_str(&self.0)
+    }
+}
+
+impl Error for CliError {}
+
+const DEFAULT_COLORMAP: &str = "viridis";
+
+pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Command, CliError> {
+    let mut args = Arguments::new(args);
+    let command = match args.positional("command")?.as_str() {
+        "run" => {
+            let mut run = RunArgs {
+                scene: args.positional("scene")?.into(),
+                output: None,
+                frame_rate: None,
+                steps: None,
+                sub_steps: None,
+                style: StyleArgs::default(),
+                video: true,
+                video_format: VideoFormat::default(),
+                frame_scale: FrameScale::default(),
+                embed_scale: false,
+                seed: None,
+                directions: None,
+                checkpoint_every: None,
+                resume: false,
+                export: None,
+                export_frames: false,
+            };
+            while let Some(flag) = args.flag()? {
+                match flag.as_str() {
+                    "--output" => run.output = Some(args.value(&flag)?),
+                    "--frame-rate" => match args.value(&flag)? {
+                        0 => return Err(CliError("`--frame-rate` must be positive".into())),
+                        frame_rate => run.frame_rate = Some(frame_rate),
+                    },
+                    "--steps" => run.steps = Some(args.value(&flag)?),
+                    "--sub-steps" => run.sub_steps = Some(args.value(&flag)?),
+                    "--no-video" => run.video = false,
+                    "--video-format" => run.video_format = args.value(&flag)?,
+                    "--frame-scale" => run.frame_scale = args.value(&flag)?,
+                    "--embed-scale" => run.embed_scale = true,
+                    "--seed" => run.seed = Some(args.value(&flag)?),
+                    "--directions" => run.directions = Some(args.value(&flag)?),
+                    "--checkpoint-every" => match args.value(&flag)? {
+                        0 => return Err(CliError("`--checkpoint-every` must be positive".into())),
+                        every => run.checkpoint_every = Some(every),
+                    },
+                    "--resume" => run.resume = true,
+                    "--export" => run.export = Some(args.value(&flag)?),
+                    "--export-frames" => run.export_frames = true,
+                    _ if style_flag(&mut run.style, &mut args, &flag)? => {}
+                    _ => return Err(unknown_flag(&flag)),
+                }
+            }
+            check_style(&run.style)?;
+            if run.export_frames && run.export.is_none() {
+                return Err(CliError("`--export-frames` needs `--export`".into()));
+            }
+            if run.video_format == VideoFormat::Pipe {
+                // Piped frames aren't saved, to resume from nor to hold metadata.
+                for (set, flag) in [
+                    (!run.video, "--no-video"),
+                    (run.resume, "--resume"),
+                    (run.embed_scale, "--embed-scale"),
+                ] {
+                    if set {
+                        return Err(CliError(format!("`--video-format pipe` excludes `{flag}`")));
+                    }
+                }
+            }
+            Command::Run(run)
+        }
+        "render" => {
+            let mut render = RenderArgs {
+                snapshot: args.positional("snapshot")?.into(),
+                output: None,
+                style: StyleArgs::default(),
+                layer: None,
+            };
+            while let Some(flag) = args.flag()? {
+                match flag.as_str() {
+                    "--output" => render.output = Some(args.value(&flag)?),
+                    "--layer" => render.layer = Some(args.value(&flag)?),
+                    _ if style_flag(&mut render.style, &mut args, &flag)? => {}
+                    _ => return Err(unknown_flag(&flag)),
+                }
+            }
+            check_style(&render.style)?;
+            Command::Render(render)
+        }
+        "export" => {
+            let mut export = ExportArgs {
+                snapshot: args.positional("snapshot")?.into(),
+                format: ArrayFormat::default(),
+                output: None,
+            };
+            while let Some(flag) = args.flag()? {
+                match flag.as_str() {
+                    "--format" => export.format = args.value(&flag)?,
+                    "--output" => export.output = Some(args.value(&flag)?),
+                    _ => return Err(unknown_flag(&flag)),
+                }
+            }
+            Command::Export(export)
+        }
+        "vtk" => {
+            let mut vtk = VtkArgs {
+                snapshot: args.positional("snapshot")?.into(),
+                format: VtkFormat::default(),
+                output: None,
+            };
+            while let Some(flag) = args.flag()? {
+                match flag.as_str() {
+                    "--format" => vtk.format = args.value(&flag)?,
+                    "--output" => vtk.output = Some(args.value(&flag)?),
+                    _ => return Err(unknown_flag(&flag)),
+                }
+            }
+            Command::Vtk(vtk)
+        }
+        "reference" => {
+            let mut reference = ReferenceArgs {
+                scene: args.positional("scene")?.into(),
+                depth: 2,
+                output: None,
+                compare: None,
+            };
+            while let Some(flag) = args.flag()? {
+                match flag.as_str() {
+                    "--depth" => reference.depth = args.value(&flag)?,
+                    "--output" => reference.output = Some(args.value(&flag)?),
+                    "--compare" => reference.compare = Some(args.value(&flag)?),
+                    _ => return Err(unknown_flag(&flag)),
+                }
+            }
+            Command::Reference(reference)
+        }
+        "solve" => {
+            let mut solve = SolveArgs {
+                scene: args.positional("scene")?.into(),
+                parameters: SolverParameters::default(),
+                output: None,
+            };
+            while let Some(flag) = args.flag()? {
+                match flag.as_str() {
+                    "--tolerance" => solve.parameters.tolerance = args.value(&flag)?,
+                    "--max-cycles" => solve.parameters.max_cycles = args.value(&flag)?,
+                    "--output" => solve.output = Some(args.value(&flag)?),
+                    _ => return Err(unknown_flag(&flag)),
+                }
+            }
+            Command::Solve(solve)
+        }
+        "inspect" => {
+            let scene = args.positional("scene")?.into();
+            if let Some(flag) = args.flag()? {
+                return Err(unknown_flag(&flag));
+            }
+            Command::Inspect { scene }
+        }
+        "help" | "--help" | "-h" => Command::Help,
+        other => return Err(CliError(format!("unknown command `{other}`"))),
+    };
+    Ok(command)
+}
+
+/// Parses `flag` if it's a rendering option, returning whether it was.
+fn style_flag<I: Iterator<Item = String>>(
+    style: &mut StyleArgs,
+    args: &mut Arguments<I>,
+    flag: &str,
+) -> Result<bool, CliError> {
+    match flag {
+        "--colormap" => style.colormap = args.value(flag)?,
+        "--colouring" => style.colouring = args.value(flag)?,
+        "--range" => style.normalization.range = args.value(flag)?,
+        "--curve" => style.normalization.curve = args.value(flag)?,
+        "--streamlines" => style.streamlines = Some(spacing(args, flag)?),
+        "--quiver" => style.quiver = Some(spacing(args, flag)?),
+        "--line-width" => style.line_width = Some(positive(args, flag)?),
+        "--no-antialiasing" => style.antialiasing = false,
+        "--portal-colour" => style.portal_colours.push(args.value(flag)?),
+        "--portal-width" => style.portal_width = Some(positive(args, flag)?),
+        "--no-portal-markers" => style.portal_markers = false,
+        "--sources" => style.sources = Some(args.value(flag)?),
+        "--source-colour" => style.source_colour = Some(args.value(flag)?),
+        _ => return Ok(false),
+    }
+    Ok(true)
+}
+
+fn check_style(style: &StyleArgs) -> Result<(), CliError> {
+    if style.source_colour.is_some() && style.sources.is_none() {
+        return Err(CliError("`--source-colour` needs `--sources`".into()));
+    }
+    Ok(())
+}
+
+/// A positive width in pixels.
+fn positive<I: Iterator<Item = String>>(
+    args: &mut Arguments<I>,
+    flag: &str,
+) -> Result<f64, CliError> {
+    match args.value(flag)? {
+        width if width > 0.0 => Ok(width),
+        _ => Err(CliError(format!("`{flag}` must be positive"))),
+    }
+}
+
+/// A positive distance in pixels.
+fn spacing<I: Iterator<Item = String>>(
+    args: &mut Arguments<I>,
+    flag: &str,
+) -> Result<u32, CliError> {
+    match args.value(flag)? {
+        0 => Err(CliError(format!("`{flag}` must be positive"))),
+        spacing => Ok(spacing),
+    }
+}
+
+fn unknown_flag(flag: &str) -> CliError {
+    CliError(format!("unknown option `{flag}`"))
+}
+
+/// Walks the arguments, splitting `--flag=value` into a flag and its value.
+struct Arguments<I: Iterator<Item = String>> {
+    args: I,
+    pending_value: Option<String>,
+}
+
+impl<I: Iterator<Item = String>> Arguments<I> {
+    fn new(args: impl IntoIterator<IntoIter = I>) -> Self {
+        Arguments {
+            args: args.into_iter(),
+            pending_value: None,
+        }
+    }
+
+    fn positional(&mut self, name: &str) -> Result<String, CliError> {
+        match self.args.next() {
+            Some(arg) if !arg.starts_with("--") => Ok(arg),
+            Some(arg) => Err(CliError(format!("expected <{name}>, found `{arg}`"))),
+            None => Err(CliError(format!("missing <{name}>"))),
+        }
+    }
+
+    fn flag(&mut self) -> Result<Option<String>, CliError> {
+        if let Some(value) = self.pending_value.take() {
+            return Err(CliError(format!("unexpected value `{value}`")));
+        }
+        let Some(arg) = self.args.next() else {
+            return Ok(None);
+        };
+        if !arg.starts_with("--") {
+            return Err(CliError(format!("unexpected argument `{arg}`")));
+        }
+        Ok(Some(match arg.split_once('=') {
+            Some((flag, value)) => {
+                self.pending_value = Some(value.to_string());
+                flag.to_string()
+            }
+            None => arg,
+        }))
+    }
+
+    fn value<T: FromStr>(&mut self, flag: &str) -> Result<T, CliError> {
+        let value = self
+            .pending_value
+            .take()
+            .or_else(|| self.args.next())
+            .ok_or_else(|| CliError(format!("missing value for `{flag}`")))?;
+        value
+            .parse()
+            .map_err(|_| CliError(format!("invalid value `{value}` for `{flag}`")))
+    }
+}
@@ -0,0 +1,112 @@
+//! Colour gradients used to render fields.
+
+use colorgrad::{
+    Color, GimpGradient, Gradient, GradientBuilder, GradientBuilderError, LinearGradient,
+    ParseGgrError, preset,
+};
+
+use std::{
+    error::Error,
+    fmt::{Display, Formatter, Result as FmtResult},
+    fs::File,
+    io::{self, BufReader},
+    path::Path,
+};
+
+macro_rules! presets {
+    ($($name:ident),* $(,)?) => {
+        /// Names accepted by [`preset`].
+        pub const PRESETS: &[&str] = &[$(stringify!($name)),*];
+
+        /// Looks up one of colorgrad's preset gradients by name.
+        pub fn preset(name: &str) -> Option<Box<dyn Gradient>> {
+            match name {
+                $(stringify!($name) => Some(preset::$name().boxed()),)*
+                _ => None,
+            }
+        }
+    };
+}
+
+presets!(
+    viridis, inferno, magma, plasma, cividis, turbo, sinebow, rainbow, warm, cool, br_bg, pr_gn,
+    pi_yg, pu_or, rd_bu, rd_gy, rd_yl_bu, rd_yl_gn, spectral, blues, greens, greys, oranges,
+    purples, reds, bu_gn, bu_pu, gn_bu, or_rd, pu_bu_gn, pu_bu, pu_rd, rd_pu, yl_gn_bu, yl_gn,
+    yl_or_br, yl_or_rd,
+);
+
+/// Builds the gradient described by `spec`:
+/// - a path to a GIMP gradient, ending in `.ggr`;
+/// - a comma-separated list of colours, named or in CSS notation, optionally followed by
+///   their positions as in a CSS `linear-gradient`, like `navy, 20%, gold, white`;
+/// - otherwise the name of one of the [`PRESETS`].
+pub fn load(spec: &str) -> Result<Box<dyn Gradient>, ColormapError> {
+    if spec.ends_with(".ggr") {
+        load_ggr(spec)
+    } else if spec.contains(',') {
+        let gradient: LinearGradient = GradientBuilder::new().css(spec).build()?;
+        Ok(gradient.boxed())
+    } else {
+        preset(spec).ok_or_else(|| ColormapError::UnknownPreset(spec.to_string()))
+    }
+}
+
+/// Reads a GIMP gradient, its foreground and background colours being black and white.
+pub fn load_ggr(path: impl AsRef<Path>) -> Result<Box<dyn Gradient>, ColormapError> {
+    let file = BufReader::new(File::open(path)?);
+    let (foreground, background) = (
+        Color::new(0.0, 0.0, 0.0, 1.0),
+        Color::new(1.0, 1.0, 1.0, 1.0),
+    );
+    Ok(GimpGradient::new(file, &foreground, &background)?.boxed())
+}
+
+#[derive(Debug)]
+pub enum ColormapError {
+    UnknownPreset(String),
+    Io(io::Error),
+    Ggr(ParseGgrError),
+    Colours(GradientBuilderError),
+}
+
+impl Display for ColormapError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        match self {
+            ColormapError::UnknownPreset(name) => write!(
+                f,
+                "unknown colormap `{name}`, expected a `.ggr` file, a list of colours or one of: {}",
+                PRESETS.join(", ")
+            ),
+            ColormapError::Io(e) => write!(f, "could not read gradient: {e}"),
+            ColormapError::Ggr(e) => write!(f, "could not parse gradient: {e}"),
+            ColormapError::Colours(e) => write!(f, "invalid colours: {e}"),
+        }
+    }
+}
+
+impl Error for ColormapError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ColormapError::UnknownPreset(_) => None,
+            ColormapError::Io(e) => Some(e),
+            ColormapError::Ggr(e) => Some(e),
+            ColormapError::Colours(e) => Some(e),
+        }
+    }
+}
+
+impl From<io::Error> for ColormapError {
+    fn from(value: io::Error) -> Self {
+        ColormapError::Io(value)
+    }
+}
+impl From<ParseGgrError> for ColormapError {
+    fn from(value: ParseGgrError) -> Self {
+        ColormapError::Ggr(value)
+    }
+}
+impl From<GradientBuilderError> for ColormapError {
+    fn from(value: GradientBuilderError) -> Self {
+        ColormapError::Colours(value)
+    }
+}
@@ -0,0 +1,224 @@
+//! Bodies moving under the field: instead of accumulating once, the field is rebuilt at
+//! every step from the gravitons alive, which sources and bodies keep emitting.
+//!
+//! The field felt by a body is thus the one emitted up to a graviton life span ago, from
+//! where the sources were then: changes propagate at the gravitons' speed.
+
+use crate::{
+    simulation::Simulation,
+    types::{Body, Point, Universe},
+};
+
+use std::{
+    fs::{self, File},
+    io::{self, BufWriter, Write},
+    path::Path,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Dynamics {
+    /// Steps to simulate.
+    pub steps: u32,
+    /// Acceleration of a body per unit of field, whatever its value: as with gravity, the
+    /// field pulls heavy and light bodies alike. Only the value's sign counts, bodies of
+    /// negative value being accelerated against the field.
+    pub strength: f64,
+}
+
+/// Accelerates every body by the field of its channel where it stands, less `own_fields`,
+/// what the body deposited there itself, then moves it, through any portal on the way.
+///
+/// The acceleration follows the field for positive bodies and opposes it for negative
+/// ones, so that on a channel like [`Channel::charge`](crate::Channel::charge) like
+/// charges repel and opposite ones attract.
+pub fn move_bodies(universe: &Universe, bodies: &mut [Body], own_fields: &[Point], strength: f64) {
+    for (body, own_field) in bodies.iter_mut().zip(own_fields) {
+        let Some(element) = universe.get_from_point(body.position) else {
+            continue;
+        };
+        let field = element.property(body.channel).field - *own_field;
+        body.velocity += field * (strength * body.value.signum());
+        (body.position, body.velocity) = universe.move_in_universe(body.position, body.velocity);
+    }
+}
+
+/// Header of the trajectory CSV files.
+const HEADER: &str = "step,body,x,y,vx,vy";
+
+/// The positions and velocities of the bodies at every step, as CSV: a row per body still
+/// in the universe, `step,body,x,y,vx,vy`.
+#[derive(Debug)]
+pub struct Trajectories<W: Write> {
+    writer: W,
+}
+
+impl Trajectories<BufWriter<File>> {
+    /// Starts `path` over, with the bodies of `simulation` where they are now.
+    pub fn create(path: impl AsRef<Path>, simulation: &Simulation) -> io::Result<Self> {
+        let mut trajectories = Trajectories::new(BufWriter::new(File::create(path)?))?;
+        trajectories.write(simulation)?;
+        Ok(trajectories)
+    }
+
+    /// Continues `path` for `simulation`, resumed from a checkpoint: the rows past its step
+    /// are dropped, to be written again. A missing file starts empty.
+    pub fn resume(path: impl AsRef<Path>, simulation: &Simulation) -> io::Result<Self> {
+        let path = path.as_ref();
+        let rows = match fs::read_to_string(path) {
+            Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+            rows => rows?,
+        };
+        let mut trajectories = Trajectories::new(BufWriter::new(File::create(path)?))?;
+        let step = simulation.step_index();
+        for row in rows.lines().skip(1) {
+            let row_step = row.split(',').next().and_then(|s| s.parse::<u32>().ok());
+            if row_step.is_some_and(|row_step| row_step <= step) {
+                writeln!(trajectories.writer, "{row}")?;
+            }
+        }
+        Ok(trajectories)
+    }
+}
+
+impl<W: Write> Trajectories<W> {
+    /// Writes the header only.
+    pub fn new(mut writer: W) -> io::Result<Self> {
+        writeln!(writer, "{HEADER}")?;
+        Ok(Trajectories { writer })
+    }
+
+    /// Appends the rows of the current step of `simulation`.
+    pub fn write(&mut self, simulation: &Simulation) -> io::Result<()> {
+        let step = simulation.step_index();
+        for (i, body) in simulation.bodies().iter().enumerate() {
+            let Body {
+                position, velocity, ..
+            } = body;
+            if body.is_inside(&simulation.universe) {
+                writeln!(
+                    self.writer,
+                    "{step},{i},{},{},{},{}",
+                    position.x, position.y, velocity.x, velocity.y
+                )?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ParticleParameters, Simulation, types::Channel};
+
+    fn simulation(channel: Channel, bodies: Vec<Body>, steps: u32) -> Simulation {
+        let universe = Universe::new(60, 60, vec![channel]);
+        Simulation::dynamic(
+            universe,
+            ParticleParameters::new(1.0, 32, 20),
+            ParticleParameters::new(0.9, 16, 10),
+            bodies,
+            Dynamics {
+                steps,
+                strength: 50.0,
+            },
+        )
+    }
+
+    fn run(channel: Channel, bodies: Vec<Body>, steps: u32) -> Vec<Body> {
+        let mut simulation = simulation(channel, bodies, steps);
+        while simulation.step() {}
+        simulation.bodies().to_vec()
+    }
+
+    #[test]
+    fn a_lone_body_stays_at_rest() {
+        let body = Body {
+            position: Point { x: 30.0, y: 30.0 },
+            value: 1.0,
+            ..Default::default()
+        };
+        let [moved] = run(Channel::mass(), vec![body], 30)[..] else {
+            unreachable!()
+        };
+        assert!(moved.velocity.magnitude() < 1e-12, "{}", moved.velocity);
+        assert!((moved.position - body.position).magnitude() < 1e-12);
+    }
+
+    #[test]
+    fn two_masses_attract() {
+        let body = |x| Body {
+            position: Point { x, y: 30.0 },
+            value: 1.0,
+            ..Default::default()
+        };
+        let [a, b] = run(Channel::mass(), vec![body(25.0), body(35.0)], 30)[..] else {
+            unreachable!()
+        };
+        assert!(a.velocity.x > 0.0 && b.velocity.x < 0.0);
+        assert!(b.position.x - a.position.x < 10.0);
+    }
+
+    #[test]
+    fn like_charges_repel_whatever_their_sign() {
+        let body = |x, value| Body {
+            position: Point { x, y: 30.0 },
+            value,
+            ..Default::default()
+        };
+        for value in [1.0, -1.0] {
+            let [a, b] = run(
+                Channel::charge(),
+                vec![body(25.0, value), body(35.0, value)],
+                30,
+            )[..] else {
+                unreachable!()
+            };
+            assert!(a.velocity.x < 0.0 && b.velocity.x > 0.0, "value {value}");
+        }
+        let [a, b] = run(
+            Channel::charge(),
+            vec![body(25.0, 1.0), body(35.0, -1.0)],
+            30,
+        )[..] else {
+            unreachable!()
+        };
+        assert!(a.velocity.x > 0.0 && b.velocity.x < 0.0);
+    }
+
+    #[test]
+    fn resumed_trajectories_drop_the_rows_past_the_checkpoint() {
+        let path = std::env::temp_dir().join(format!("trajectories-{}.csv", std::process::id()));
+        let body = |x| Body {
+            position: Point { x, y: 30.0 },
+            value: 1.0,
+            ..Default::default()
+        };
+        let mut simulation = simulation(Channel::mass(), vec![body(25.0), body(35.0)], 4);
+        let mut trajectories = Trajectories::create(&path, &simulation).unwrap();
+        let mut checkpoint = None;
+        while simulation.step() {
+            trajectories.write(&simulation).unwrap();
+            if simulation.step_index() == 2 {
+                checkpoint = Some(simulation.clone());
+            }
+        }
+        trajectories.flush().unwrap();
+        let full = fs::read_to_string(&path).unwrap();
+
+        let mut resumed = checkpoint.unwrap();
+        let mut trajectories = Trajectories::resume(&path, &resumed).unwrap();
+        while resumed.step() {
+            trajectories.write(&resumed).unwrap();
+        }
+        trajectories.flush().unwrap();
+        let again = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(full.lines().count(), 1 + 5 * 2);
+        assert_eq!(again, full);
+    }
+}
@@ -0,0 +1,419 @@
+//! Field propagation by particles: gravitons leave the sources and, at every step, emit
+//! sub-gravitons which deposit the field along their path.
+
+use crate::{
+    random::SplitMix64,
+    types::{Body, Particle, Point, Universe},
+};
+
+use core::{f64::consts::TAU, str::FromStr};
+use std::borrow::Cow;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ParticleParameters {
+    pub step_size: f64,
+    pub quantity: u32,
+    pub life_span: u32,
+}
+
+impl ParticleParameters {
+    pub const fn new(step_size: f64, quantity: u32, life_span_steps: u32) -> Self {
+        Self {
+            step_size,
+            quantity,
+            life_span: (life_span_steps as f64 / step_size).ceil() as u32,
+            // life_span: life_span_steps,
+        }
+    }
+}
+
+/// How emitters orient the particles they spread around them.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Emission {
+    pub directions: Directions,
+    /// Seed turning the directions by a random amount for every emitter and step, if any:
+    /// this breaks the rays, and averaging runs with different seeds smooths the field out.
+    /// [`Directions::Random`] ones are drawn from it instead, `0` if unset.
+    pub seed: Option<u64>,
+}
+
+/// Angles of the `quantity` particles leaving an emitter, before any rotation.
+///
+/// Sequences are continued from another index by every emitter at every step, seeded or
+/// not, so that emitters don't share their directions: the rays of the even ones are broken
+/// up.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Directions {
+    /// Evenly spaced, `i × τ / quantity`.
+    #[default]
+    Even,
+    /// Successive multiples of the golden angle, `i × (2 - φ) × τ`.
+    GoldenAngle,
+    /// The van der Corput sequence of base 3, that is the base 3 digits of `i` mirrored
+    /// after the point, times `τ`. In base 2, the powers of two quantities usually emitted
+    /// would get the even angles, merely shuffled.
+    Halton,
+    /// Drawn independently for every particle of every emitter and step.
+    Random,
+}
+
+impl Directions {
+    /// Angle of the `i`th of `quantity` directions, none being fixed if random.
+    fn angle(self, i: u64, quantity: u32) -> f64 {
+        match self {
+            Directions::Random => unreachable!("random directions are drawn per emitter"),
+            Directions::Even => i as f64 * (TAU / quantity as f64),
+            Directions::GoldenAngle => i.wrapping_mul(GOLDEN_FRACTION) as f64 / 2f64.powi(64) * TAU,
+            Directions::Halton => {
+                let (mut i, mut digit, mut angle) = (i, 1.0 / 3.0, 0.0);
+                while i > 0 {
+                    angle += (i % 3) as f64 * digit;
+                    (i, digit) = (i / 3, digit / 3.0);
+                }
+                angle * TAU
+            }
+        }
+    }
+}
+
+impl FromStr for Directions {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "even" => Ok(Directions::Even),
+            "golden" => Ok(Directions::GoldenAngle),
+            "halton" => Ok(Directions::Halton),
+            "random" => Ok(Directions::Random),
+            _ => Err(format!(
+                "unknown directions `{s}`, expected `even`, `golden`, `halton` or `random`"
+            )),
+        }
+    }
+}
+
+/// `(2 - φ) × 2⁶⁴`: the golden angle, as a fraction of a turn, in fixed point, whose
+/// multiples wrap around exactly.
+const GOLDEN_FRACTION: u64 = 0x61C8_8646_80B5_83EB;
+
+/// Kinds of emitters, so that their random streams differ.
+const SOURCE: u64 = 0;
+const BODY: u64 = 1;
+const GRAVITON: u64 = 2;
+
+impl Emission {
+    /// Directions of the particles leaving the emitter identified by `keys` at `step`: the
+    /// precomputed `set` if even, the part of the sequence starting at an index of the
+    /// emitter's own, or random ones; then turned by a random amount if seeded.
+    fn emitter<'a>(
+        self,
+        set: &'a [Point],
+        parameters: &ParticleParameters,
+        step: u32,
+        keys: [u64; 3],
+    ) -> Cow<'a, [Point]> {
+        let [kind, a, b] = keys;
+        let mut rng = SplitMix64::stream(self.seed.unwrap_or(0), &[step as u64, kind, a, b]);
+        let (quantity, step_size) = (parameters.quantity, parameters.step_size);
+        let directions = match self.directions {
+            Directions::Random => {
+                return (0..quantity)
+                    .map(|_| Point::from_angle(rng.next_f64() * TAU) * step_size)
+                    .collect();
+            }
+            Directions::Even => Cow::Borrowed(set),
+            Directions::GoldenAngle | Directions::Halton => {
+                let start = rng.next_u64();
+                (0..quantity as u64)
+                    .map(|i| {
+                        let angle = self.directions.angle(start.wrapping_add(i), quantity);
+                        Point::from_angle(angle) * step_size
+                    })
+                    .collect()
+            }
+        };
+        if self.seed.is_none() {
+            return directions;
+        }
+        let period = match self.directions {
+            // Even directions repeat after one spacing.
+            Directions::Even => TAU / quantity as f64,
+            _ => TAU,
+        };
+        let turn = Point::from_angle(rng.next_f64() * period);
+        directions
+            .iter()
+            .map(|&direction| direction * turn)
+            .collect()
+    }
+
+    /// The directions shared by every emitter before any rotation, of length `step_size`:
+    /// only even ones are, the others being picked per emitter.
+    fn directions(self, parameters: &ParticleParameters) -> Box<[Point]> {
+        if self.directions != Directions::Even {
+            return Box::default();
+        }
+        let quantity = parameters.quantity;
+        (0..quantity as u64)
+            .map(|i| Point::from_angle(self.directions.angle(i, quantity)) * parameters.step_size)
+            .collect()
+    }
+}
+
+/// Emits gravitons from every source, with the parameters of its channel in `gravitons`.
+pub fn spawn(
+    universe: &mut Universe,
+    gravitons: &[ParticleParameters],
+    emission: Emission,
+    step: u32,
+) -> Box<[Particle]> {
+    let mut particles: Vec<Particle> = Vec::new();
+    let speeds: Box<[Box<[Point]>]> = gravitons.iter().map(|p| emission.directions(p)).collect();
+    universe.for_each_element(|(x, y), size, element| {
+        for (channel, speeds) in speeds.iter().enumerate() {
+            let mass = element.property(channel).value;
+            if !mass.is_normal() {
+                continue;
+            }
+            // Values are densities, spread over the whole region of the element.
+            let mass = mass * universe.area((x, y), size);
+            let offset = (size - 1) as f64 / 2.0;
+            let position = Point {
+                x: x as f64 + offset,
+                y: y as f64 + offset,
+            };
+            let key = (y as u64) << 32 | x as u64;
+            let keys = [SOURCE, key, channel as u64];
+            let speeds = emission.emitter(speeds, &gravitons[channel], step, keys);
+            emit(&mut particles, position, mass, channel, None, &speeds);
+        }
+    });
+    particles.into()
+}
+
+/// Emits gravitons from every body still in the universe.
+pub fn spawn_from_bodies(
+    universe: &Universe,
+    bodies: &[Body],
+    gravitons: &[ParticleParameters],
+    emission: Emission,
+    step: u32,
+) -> Box<[Particle]> {
+    let mut particles: Vec<Particle> = Vec::new();
+    let speeds: Box<[Box<[Point]>]> = gravitons.iter().map(|p| emission.directions(p)).collect();
+    for (i, body) in bodies.iter().enumerate() {
+        if !body.is_inside(universe) || !body.value.is_normal() {
+            continue;
+        }
+        let keys = [BODY, i as u64, 0];
+        let speeds = emission.emitter(&speeds[body.channel], &gravitons[body.channel], step, keys);
+        emit(
+            &mut particles,
+            body.position,
+            body.value,
+            body.channel,
+            Some(i),
+            &speeds,
+        );
+    }
+    particles.into()
+}
+
+/// Shares `mass` between gravitons leaving `position` at each of `speeds`, from `body` if
+/// any.
+fn emit(
+    particles: &mut Vec<Particle>,
+    position: Point,
+    mass: f64,
+    channel: usize,
+    body: Option<usize>,
+    speeds: &[Point],
+) {
+    let inv = 1.0 / speeds.len() as f64;
+    for &speed in speeds {
+        particles.push(Particle {
+            position,
+            speed,
+            value: mass * inv,
+            channel,
+            age: 0,
+            body,
+        });
+    }
+}
+
+/// Moves the gravitons, which deposit the field of their channel through sub-gravitons
+/// following the parameters in `sub_gravitons`. `step` is the one being done, from `0`.
+///
+/// What the gravitons of each of `bodies` deposit in the element the body stands in is
+/// also added to its entry of `own_fields`, for it not to feel its own field.
+pub fn advance(
+    universe: &mut Universe,
+    particles: &mut [Particle],
+    bodies: &[Body],
+    own_fields: &mut [Point],
+    sub_gravitons: &[ParticleParameters],
+    emission: Emission,
+    step: u32,
+) -> Box<[Particle]> {
+    let directions: Box<[Box<[Point]>]> = sub_gravitons
+        .iter()
+        .map(|p| emission.directions(p))
+        .collect();
+    let cells: Box<[Option<(u32, u32)>]> = bodies
+        .iter()
+        .map(|b| universe.cell_origin(b.position))
+        .collect();
+
+    particles
+        .iter_mut()
+        .enumerate()
+        .filter_map(|(i, particle)| {
+            particle.move_in_universe_mut(universe);
+            particle.age += 1;
+            if !particle.position.is_inside(universe) {
+                return None;
+            }
+            //* spawn field
+            let parameters = &sub_gravitons[particle.channel];
+            let keys = [GRAVITON, i as u64, 0];
+            let directions =
+                emission.emitter(&directions[particle.channel], parameters, step, keys);
+            let mass = particle.value / directions.len() as f64;
+            let own_cell = particle.body.and_then(|b| cells[b]);
+            for &direction in directions.iter() {
+                let own = process_sub_graviton(
+                    universe,
+                    particle.position,
+                    direction,
+                    mass,
+                    particle.channel,
+                    &sub_gravitons[particle.channel],
+                    own_cell,
+                );
+                if let Some(body) = particle.body {
+                    own_fields[body] += own;
+                }
+            }
+            Some(*particle)
+        })
+        .collect()
+}
+
+/// Deposits the field along the path of a sub-graviton, returning the part of it left in
+/// the element at `own_cell`, if any.
+#[inline]
+fn process_sub_graviton(
+    universe: &mut Universe,
+    position: Point,
+    dir_sub_graviton: Point,
+    mass: f64,
+    channel: usize,
+    sub_graviton: &ParticleParameters,
+    own_cell: Option<(u32, u32)>,
+) -> Point {
+    let coupling = universe.channels()[channel].coupling;
+    let mut position = position;
+    let mut dir_sub_graviton = dir_sub_graviton * sub_graviton.step_size;
+    let mut own = Point::default();
+    for _age in 0..sub_graviton.life_span {
+        if !position.is_inside(universe) {
+            break;
+        }
+        // Larger elements are crossed by proportionally more sub-gravitons.
+        let (element, area) = universe.get_cell_from_point_mut(position).unwrap();
+        let deposit = dir_sub_graviton * (coupling * mass / area);
+        element.property_mut(channel).field += deposit;
+        if own_cell.is_some() && universe.cell_origin(position) == own_cell {
+            own += deposit;
+        }
+        // Advance sub-graviton's position
+        (position, dir_sub_graviton) = universe.move_in_universe(position, dir_sub_graviton);
+    }
+    own
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PARAMETERS: ParticleParameters = ParticleParameters::new(1.0, 16, 10);
+
+    /// Angles of the directions of an emitter, in `[0, τ)`, sorted.
+    fn angles(emission: Emission, step: u32, keys: [u64; 3]) -> Vec<f64> {
+        let set = emission.directions(&PARAMETERS);
+        let mut angles: Vec<f64> = (emission.emitter(&set, &PARAMETERS, step, keys).iter())
+            .map(|d| d.y.atan2(d.x).rem_euclid(TAU))
+            .collect();
+        angles.sort_by(f64::total_cmp);
+        angles
+    }
+
+    /// Gaps between successive angles, around the circle.
+    fn gaps(angles: &[f64]) -> Vec<f64> {
+        let mut gaps: Vec<f64> = angles.windows(2).map(|w| w[1] - w[0]).collect();
+        gaps.push(angles[0] + TAU - angles[angles.len() - 1]);
+        gaps
+    }
+
+    #[test]
+    fn rotated_emitters_turn_the_even_set() {
+        let emission = Emission {
+            directions: Directions::Even,
+            seed: Some(7),
+        };
+        let (a, b) = (
+            angles(emission, 0, [SOURCE, 1, 0]),
+            angles(emission, 0, [SOURCE, 2, 0]),
+        );
+        assert_ne!(a, b);
+        for angles in [a, b] {
+            let spacing = TAU / PARAMETERS.quantity as f64;
+            assert!(gaps(&angles).iter().all(|g| (g - spacing).abs() < 1e-9));
+        }
+    }
+
+    #[test]
+    fn random_directions_are_drawn_for_every_particle() {
+        let emission = Emission {
+            directions: Directions::Random,
+            seed: None,
+        };
+        let a = angles(emission, 0, [SOURCE, 1, 0]);
+        assert_eq!(a.len(), PARAMETERS.quantity as usize);
+        // Unevenly spread, unlike any turned set.
+        let gaps = gaps(&a);
+        let spacing = TAU / PARAMETERS.quantity as f64;
+        assert!(gaps.iter().any(|g| (g - spacing).abs() > spacing / 4.0));
+        // Differing between emitters and steps, reproducibly.
+        assert_ne!(a, angles(emission, 0, [SOURCE, 2, 0]));
+        assert_ne!(a, angles(emission, 1, [SOURCE, 1, 0]));
+        assert_eq!(a, angles(emission, 0, [SOURCE, 1, 0]));
+    }
+
+    #[test]
+    fn sequences_differ_between_emitters_without_a_seed() {
+        for directions in [Directions::GoldenAngle, Directions::Halton] {
+            let emission = Emission {
+                directions,
+                seed: None,
+            };
+            let a = angles(emission, 0, [SOURCE, 1, 0]);
+            assert_ne!(a, angles(emission, 0, [SOURCE, 2, 0]), "{directions:?}");
+            assert_ne!(a, angles(emission, 1, [SOURCE, 1, 0]), "{directions:?}");
+            // Still spread around the circle.
+            let spacing = TAU / PARAMETERS.quantity as f64;
+            assert!(
+                gaps(&a).iter().all(|&g| g < 3.0 * spacing),
+                "{directions:?}"
+            );
+        }
+        // Not even, though a power of two.
+        let halton = Emission {
+            directions: Directions::Halton,
+            seed: None,
+        };
+        let spacing = TAU / PARAMETERS.quantity as f64;
+        let gaps = gaps(&angles(halton, 0, [SOURCE, 1, 0]));
+        assert!(gaps.iter().any(|g| (g - spacing).abs() > spacing / 4.0));
+    }
+}
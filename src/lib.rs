@@ -0,0 +1,39 @@
+//! Simulation of fields in a 2D universe connected by portals.
+//!
+//! A [`Universe`] is a grid of cells holding sources and their accumulated field, for each
+//! of its [`Channel`]s, plus
+//! [`PortalSet`]s teleporting anything crossing one of their portals to the other.
+//! A [`Simulation`] builds the field with [`gravitons`], and [`render`] turns it into images.
+//!
+//! ```no_run
+//! use simulador_de_fluxo::{Simulation, scene::Scene};
+//!
+//! let Scene { universe, settings, .. } = Scene::load("scenes/default.toml").unwrap();
+//! let mut simulation = Simulation::new(universe, settings.graviton, settings.sub_graviton);
+//! while simulation.step() {}
+//! simulation.universe.to_image().save("field.png").unwrap();
+//! ```
+
+pub mod checkpoint;
+pub mod colormap;
+pub mod dynamics;
+pub mod gravitons;
+pub mod numpy;
+pub mod poisson;
+pub mod random;
+pub mod reference;
+pub mod render;
+pub mod scene;
+pub mod simulation;
+pub mod snapshot;
+pub mod types;
+pub mod video;
+pub mod vtk;
+
+pub use self::{
+    gravitons::{Emission, ParticleParameters},
+    simulation::Simulation,
+    types::{
+        Body, Channel, Element, Particle, Point, Portal, PortalSet, Property, Region, Universe,
+    },
+};
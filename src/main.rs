@@ -1,242 +1,557 @@
-mod types;
-use types::*;
+mod cli;
+use cli::{Command, ExportArgs, ReferenceArgs, RenderArgs, RunArgs, SolveArgs, StyleArgs, VtkArgs};
 
-use std::{fs, process::Command};
+use simulador_de_fluxo::{
+    Emission, ParticleParameters, PortalSet, Simulation, Universe, checkpoint, colormap,
+    dynamics::Trajectories,
+    gravitons::Directions,
+    numpy, poisson, reference,
+    render::{self, FrameScales, Quiver, Sources, Streamlines, Stroke, Style},
+    scene::Scene,
+    snapshot,
+    video::{self, FfmpegPipe, VideoFormat},
+    vtk,
+};
+
+use std::{
+    env,
+    error::Error,
+    path::{Path, PathBuf},
+    process,
+};
+
+use colorgrad::Color;
+use image::Rgb;
+
+type Result<T = ()> = std::result::Result<T, Box<dyn Error>>;
 
 fn main() {
-    //* Preparating simulation universe
-    println!("Preparating simulation universe...");
-    let mut universe = {
-        const WIDTH: u32 = 540 - 1;
-        const HEIGHT: u32 = 540 - 1;
-        const OBJECT_RADIUS: f64 = 4.0;
-        let mut universe = Universe::new(WIDTH, HEIGHT);
-
-        let center = Point {
-            x: universe.width as f64 / 2.0,
-            y: universe.height as f64 / 2.0,
-        };
-        //? Two masses.
-        // let t = (WIDTH as f64 * 0.125) as u32;
-        // universe[(t, HEIGHT >> 1)].element_mut().unwrap().mass.value = 1.0;
-        // universe[(WIDTH - t, HEIGHT >> 1)]
-        //     .element_mut()
-        //     .unwrap()
-        //     .mass
-        //     .value = 1.0;
-        //? Circle with mass
-        for y in (-OBJECT_RADIUS) as i64..=OBJECT_RADIUS as i64 {
-            for x in (-OBJECT_RADIUS) as i64..=OBJECT_RADIUS as i64 {
-                if (x as f64).hypot(y as f64) <= OBJECT_RADIUS {
-                    let x = (center.x as i64 + x) as u32;
-                    let y = (center.y as i64 + y) as u32;
-                    universe[(x, y)].element_mut().unwrap().mass.value = 1.0;
-                }
-            }
+    let command = match cli::parse(env::args().skip(1)) {
+        Ok(command) => command,
+        Err(e) => {
+            eprintln!("{e}\n\n{}", cli::USAGE);
+            process::exit(2);
         }
-
-        let portalset = {
-            let delta = universe.width as f64 / 6.0;
-            let portal_height = universe.height as f64 / 2.0;
-            PortalSet::new(
-                Portal::new(
-                    center + (-delta, -portal_height / 2.0),
-                    center + (delta, -portal_height / 2.0),
-                ),
-                Portal::new(
-                    center + (-delta, portal_height / 2.0),
-                    center + (delta, portal_height / 2.0),
-                ),
-            )
-        };
-        // println!(
-        //     "Portalset {{ {} - {} <-> {} - {} }}",
-        //     portalset.a.point_a, portalset.a.point_b, portalset.b.point_a, portalset.b.point_b
-        // );
-        universe.add_portal_set(portalset);
-        universe
     };
+    let result = match command {
+        Command::Run(args) => run(args),
+        Command::Render(args) => render(args),
+        Command::Export(args) => export(args),
+        Command::Vtk(args) => export_vtk(args),
+        Command::Reference(args) => compute_reference(args),
+        Command::Solve(args) => solve(args),
+        Command::Inspect { scene } => inspect(&scene),
+        Command::Help => {
+            println!("{}", cli::USAGE);
+            Ok(())
+        }
+    };
+    if let Err(e) = result {
+        eprintln!("{e}");
+        process::exit(1);
+    }
+}
 
-    //* Remove previous images
-    println!("Clearing previous images");
-    match fs::exists(FOLDER) {
-        Ok(true) => {
-            for f in fs::read_dir(FOLDER).unwrap() {
-                let path = f.unwrap().path();
-                if path.extension().unwrap() == "png" {
-                    fs::remove_file(path).unwrap();
-                }
+fn load_scene(path: &Path) -> Result<Scene> {
+    println!("Loading scene {}...", path.display());
+    Scene::load(path).map_err(|e| format!("Failed to load scene `{}`: {e}", path.display()).into())
+}
+
+fn run(args: RunArgs) -> Result {
+    let style = load_style(&args.style)?;
+    let Scene {
+        mut universe,
+        bodies,
+        mut settings,
+    } = load_scene(&args.scene)?;
+    if let Some(output) = args.output {
+        settings.output = output;
+    }
+    if let Some(frame_rate) = args.frame_rate {
+        settings.frame_rate = frame_rate;
+    }
+    if let Some(seed) = args.seed {
+        settings.emission.seed = Some(seed);
+    }
+    if let Some(directions) = args.directions {
+        settings.emission.directions = directions;
+    }
+    // Step overrides apply to every channel.
+    if let Some(steps) = args.steps {
+        settings.graviton.life_span = steps;
+        for channel in universe.channels_mut() {
+            if let Some(graviton) = &mut channel.graviton {
+                graviton.life_span = steps;
+            }
+        }
+    }
+    if let Some(steps) = args.sub_steps {
+        settings.sub_graviton.life_span = steps;
+        for channel in universe.channels_mut() {
+            if let Some(sub_graviton) = &mut channel.sub_graviton {
+                sub_graviton.life_span = steps;
+            }
+        }
+    }
+    let checkpoint = settings.output.join(CHECKPOINT);
+    let resumed = args.resume && checkpoint.exists();
+    let mut simulation = if resumed {
+        println!("Resuming from {}", checkpoint.display());
+        checkpoint::load_checkpoint(&checkpoint)
+            .map_err(|e| format!("Failed to load checkpoint `{}`: {e}", checkpoint.display()))?
+    } else {
+        let (graviton, sub_graviton) = (settings.graviton, settings.sub_graviton);
+        match settings.dynamics {
+            Some(dynamics) => {
+                Simulation::dynamic(universe, graviton, sub_graviton, bodies, dynamics)
+            }
+            None => Simulation::new(universe, graviton, sub_graviton),
+        }
+        .with_emission(settings.emission)
+    };
+    let universe = &simulation.universe;
+    let layers: Vec<(usize, PathBuf)> = (0..universe.channels().len())
+        .map(|channel| (channel, layer_folder(&settings.output, universe, channel)))
+        .collect();
+    let arrays = settings.output.join(ARRAYS);
+    if let Some(frame) = args.frame_scale.ahead(simulation.steps()) {
+        println!("Simulating ahead to fit the colours to frame {frame}");
+    }
+    let mut scales = FrameScales::new(&simulation, style.normalization, args.frame_scale)?;
+    // Streamed to ffmpeg instead of saved, if piping.
+    let mut pipes = Vec::new();
+    if args.video && args.video_format == VideoFormat::Pipe {
+        let (width, height) = (simulation.universe.width, simulation.universe.height);
+        for (_, folder) in &layers {
+            let video = folder.with_extension(args.video_format.extension());
+            let pipe = FfmpegPipe::spawn(width, height, settings.frame_rate, &video)
+                .map_err(|e| format!("Failed to start ffmpeg: {e}"))?;
+            pipes.push(pipe);
+        }
+    }
+    let mut save_frames = |universe: &Universe, i: u32| -> Result {
+        for (channel, folder) in &layers {
+            let scale = scales.fit(universe, *channel);
+            let image = universe.to_scaled_image(*channel, &style, &scale);
+            let path = video::frame_path(folder, i);
+            if let Some(pipe) = pipes.get_mut(*channel) {
+                pipe.write_frame(&image.to_rgb8())
+                    .map_err(|e| format!("Failed to stream frame {i} to ffmpeg: {e}"))?;
+            } else if args.embed_scale {
+                render::save_with_scale(&image, &scale, path)?;
+            } else {
+                image.save(path)?;
             }
         }
-        _ => fs::create_dir_all(FOLDER).expect("Failed to create directory"),
+        if let (Some(format), true) = (args.export, args.export_frames) {
+            let path = array_path(&video::frame_path(&arrays, i), format);
+            numpy::save_arrays(universe, path, format)?;
+        }
+        Ok(())
     };
+    if args.export.is_some() {
+        std::fs::create_dir_all(&arrays)?;
+    }
+
+    let trajectories_path = settings.output.join(TRAJECTORIES);
+    let mut trajectories = None;
+    if resumed {
+        // Frames past the checkpoint get overwritten, rows past it must go.
+        if simulation.dynamics().is_some() {
+            trajectories = Some(Trajectories::resume(&trajectories_path, &simulation)?);
+        }
+    } else {
+        //* Remove previous images
+        println!("Clearing previous images");
+        for (_, folder) in &layers {
+            video::clear_frames(folder)?;
+        }
+        if simulation.dynamics().is_some() {
+            trajectories = Some(Trajectories::create(&trajectories_path, &simulation)?);
+        }
+        save_frames(&simulation.universe, 0)?;
+    }
 
     //* Run simulation
     println!("Running simulation");
-    {
-        let mut gravitons = gravitons::spawn(&mut universe, GRAVITON.quantity, GRAVITON.step_size);
-        universe
-            .to_image()
-            .save(format!("{}/{:04}.png", FOLDER, 0))
-            .expect("Failed to save image");
-        for i in 0..GRAVITON.life_span {
-            println!(
-                "Step {} / {} ≃ {}%",
-                i + 1,
-                GRAVITON.life_span,
-                (i + 1) * 100 / GRAVITON.life_span
-            );
-            gravitons = gravitons::advance(
-                &mut universe,
-                gravitons,
-                SUB_GRAVITON.quantity,
-                SUB_GRAVITON.step_size,
-            );
-            universe
-                .to_image()
-                .save(format!("{}/{:04}.png", FOLDER, i + 1))
-                .expect("Failed to save image");
+    while simulation.step() {
+        let (i, steps) = (simulation.step_index(), simulation.steps());
+        println!("Step {} / {} ≃ {}%", i, steps, i * 100 / steps);
+        save_frames(&simulation.universe, i)?;
+        if let Some(trajectories) = &mut trajectories {
+            trajectories.write(&simulation)?;
         }
+        if args.checkpoint_every.is_some_and(|every| i % every == 0) {
+            if let Some(trajectories) = &mut trajectories {
+                trajectories.flush()?;
+            }
+            checkpoint::save_checkpoint(&simulation, &checkpoint)?;
+            println!("Saved checkpoint at step {i}");
+        }
+    }
+    if let Some(mut trajectories) = trajectories {
+        trajectories.flush()?;
+        println!("Saved body trajectories to {}", trajectories_path.display());
+    }
+    let universe = simulation.into_universe();
+    let snapshot = settings.output.join(SNAPSHOT);
+    snapshot::save_snapshot(&universe, &snapshot)?;
+    println!("Saved final fields to {}", snapshot.display());
+    if let Some(format) = args.export {
+        let path = array_path(&arrays.join(SNAPSHOT), format);
+        numpy::save_arrays(&universe, &path, format)?;
+        println!("Saved final arrays to {}", path.display());
     }
 
+    if !args.video {
+        return Ok(());
+    }
     //* Join images into video
+    if args.video_format == VideoFormat::Pipe {
+        println!("Waiting for ffmpeg to finish the video");
+        for pipe in pipes {
+            pipe.finish()
+                .map_err(|e| format!("Failed to encode the video: {e}"))?;
+        }
+        return Ok(());
+    }
     println!("Joining images into video");
-    {
-        let mut cmd = Command::new("ffmpeg");
-        let params = [
-            "-framerate",
-            "30",
-            "-i",
-            &format!("{FOLDER}/%04d.png"),
-            "-c:v",
-            "libx264",
-            "-pix_fmt",
-            "yuv420p",
-            "-vf",
-            "\"scale=trunc(iw/2)*2:trunc(ih/2)*2\"",
-            "./output.mp4",
-        ];
-        cmd.args(params);
-        if cmd.output().is_err() {
-            eprintln!("Failed to join images into video");
-            println!("Run `ffmpeg {:?}` manually", params);
+    let mut failures = 0;
+    for (_, folder) in &layers {
+        let video = folder.with_extension(args.video_format.extension());
+        if let Err(e) = args.video_format.join(folder, settings.frame_rate, &video) {
+            eprintln!("Failed to join images into video: {e}");
+            failures += 1;
+            if args.video_format == VideoFormat::Mp4 {
+                println!(
+                    "Run `ffmpeg {}` manually",
+                    video::ffmpeg_arguments(folder, settings.frame_rate, &video)
+                        .iter()
+                        .map(|arg| shell_quote(arg))
+                        .collect::<Vec<_>>()
+                        .join(" ")
+                );
+            }
+        } else {
+            println!("Saved {}", video.display());
         }
     }
+    match failures {
+        0 => Ok(()),
+        _ => Err(format!("Failed to join {failures} of {} videos", layers.len()).into()),
+    }
 }
 
-pub struct ParticleParameters {
-    pub step_size: f64,
-    pub quantity: u32,
-    pub life_span: u32,
+/// `arg` as a shell would read it back.
+fn shell_quote(arg: &str) -> String {
+    let plain = |c: char| c.is_ascii_alphanumeric() || "-_./:%=+".contains(c);
+    if !arg.is_empty() && arg.chars().all(plain) {
+        arg.to_string()
+    } else {
+        format!("'{}'", arg.replace('\'', r"'\''"))
+    }
 }
 
-impl ParticleParameters {
-    pub const fn new(step_size: f64, quantity: u32, life_span_steps: u32) -> Self {
-        Self {
-            step_size,
-            quantity,
-            life_span: (life_span_steps as f64 / step_size).ceil() as u32,
-            // life_span: life_span_steps,
+/// Frames of the first channel go straight in the output folder, the others in subfolders.
+fn layer_folder(output: &Path, universe: &Universe, channel: usize) -> PathBuf {
+    match channel {
+        0 => output.to_path_buf(),
+        _ => output.join(&universe.channels()[channel].name),
+    }
+}
+
+/// Name of the snapshot written in the output folder at the end of a run.
+const SNAPSHOT: &str = "final.snap";
+/// Name of the checkpoint written in the output folder, and resumed from.
+const CHECKPOINT: &str = "checkpoint.ckpt";
+/// Name of the CSV file receiving the bodies' positions and velocities in dynamic runs.
+const TRAJECTORIES: &str = "bodies.csv";
+/// Name of the folder receiving NumPy arrays in the output folder, with `--export`. It and
+/// the stems of the snapshots are channel names reserved by `types::RESERVED_NAMES`.
+const ARRAYS: &str = "arrays";
+
+fn render(args: RenderArgs) -> Result {
+    let style = load_style(&args.style)?;
+    let universe = snapshot::load_snapshot(&args.snapshot)
+        .map_err(|e| format!("Failed to load snapshot `{}`: {e}", args.snapshot.display()))?;
+    let output = args
+        .output
+        .unwrap_or_else(|| args.snapshot.with_extension("png"));
+    let channel = match &args.layer {
+        Some(name) => universe.channel_index(name).ok_or_else(|| {
+            let names: Vec<&str> = universe.channels().iter().map(|c| &*c.name).collect();
+            format!(
+                "Unknown channel `{name}`, expected one of: {}",
+                names.join(", ")
+            )
+        })?,
+        None => 0,
+    };
+    universe.to_styled_image(channel, &style).save(&output)?;
+    println!("Rendered {}", output.display());
+    Ok(())
+}
+
+fn load_style(args: &StyleArgs) -> Result<Style> {
+    let mut style = Style {
+        gradient: colormap::load(&args.colormap)
+            .map_err(|e| format!("Failed to load colormap `{}`: {e}", args.colormap))?,
+        colouring: args.colouring,
+        normalization: args.normalization,
+        ..Style::default()
+    };
+    let stroke = Stroke {
+        width: args.line_width.unwrap_or(1.0),
+        antialiased: args.antialiasing,
+    };
+    style.streamlines = args.streamlines.map(|spacing| Streamlines {
+        stroke,
+        ..Streamlines::new(spacing)
+    });
+    style.quiver = args.quiver.map(|spacing| Quiver {
+        stroke,
+        ..Quiver::new(spacing)
+    });
+    let rgb = |colour: &Color| {
+        let [r, g, b, _] = colour.to_rgba8();
+        Rgb([r, g, b])
+    };
+    if !args.portal_colours.is_empty() {
+        style.portals.colours = args.portal_colours.iter().map(rgb).collect();
+    }
+    if let Some(width) = args.portal_width {
+        style.portals.width = width;
+    }
+    style.portals.markers = args.portal_markers;
+    if let Some(blend) = args.sources {
+        let mut sources = Sources::new(blend);
+        if let Some(colour) = &args.source_colour {
+            sources.colour = rgb(colour);
         }
+        style.sources = Some(sources);
     }
+    Ok(style)
 }
 
-static GRAVITON: ParticleParameters = ParticleParameters::new(3.0, 512, 800);
-static SUB_GRAVITON: ParticleParameters = ParticleParameters::new(0.9, 256, 100);
-const FOLDER: &str = "output";
-
-mod gravitons {
-    use crate::process_sub_graviton;
-
-    use super::types::{Particle, Point, Universe};
-
-    use core::f64::consts::TAU;
-
-    pub fn spawn(
-        universe: &mut Universe,
-        ammount_per_mass_point: u32,
-        particle_speed: f64,
-    ) -> Box<[Particle]> {
-        let inv = 1.0 / ammount_per_mass_point as f64;
-        let k: f64 = TAU * inv;
-        let mut particles: Vec<Particle> = Vec::new();
-        let speeds: Box<[Point]> = (0..ammount_per_mass_point)
-            .map(|i| Point::from_angle(i as f64 * k) * particle_speed)
-            .collect();
-        for y in 0..universe.height {
-            for x in 0..universe.width {
-                let element = universe[(x, y)].element().unwrap();
-                let mass = element.mass.value;
-                if !mass.is_normal() {
-                    continue;
-                }
-                let position = Point {
-                    x: x as f64,
-                    y: y as f64,
-                };
-                for i in 0..ammount_per_mass_point as usize {
-                    particles.push(Particle {
-                        position,
-                        speed: *unsafe { speeds.get_unchecked(i) },
-                        value: mass * inv,
-                    });
-                }
-            }
+/// Name of the reference snapshot written in the scene's output folder by default.
+const REFERENCE: &str = "reference.snap";
+
+fn compute_reference(args: ReferenceArgs) -> Result {
+    let Scene {
+        universe, settings, ..
+    } = load_scene(&args.scene)?;
+    println!(
+        "Computing reference field through up to {} portals",
+        args.depth
+    );
+    let reference = reference::reference_field(&universe, args.depth);
+    let output = match args.output {
+        Some(output) => output,
+        None => {
+            std::fs::create_dir_all(&settings.output)?;
+            settings.output.join(REFERENCE)
         }
-        particles.into()
-    }
-
-    pub fn advance(
-        universe: &mut Universe,
-        mut particles: Box<[Particle]>,
-        ammount_per_particle: u32,
-        sub_particle_speed: f64,
-    ) -> Box<[Particle]> {
-        let inv = 1.0 / ammount_per_particle as f64;
-        let k: f64 = TAU * inv;
-        let directions: Box<[Point]> = (0..ammount_per_particle)
-            .map(|i| Point::from_angle(i as f64 * k) * sub_particle_speed)
-            .collect();
-
-        particles
-            .iter_mut()
-            .filter_map(|particle| {
-                particle.move_in_universe_mut(universe);
-                if !particle.position.is_inside(universe) {
-                    return None;
-                }
-                //* spawn field
-                let mass = particle.value * inv;
-                for i in 0..ammount_per_particle as usize {
-                    process_sub_graviton(
-                        universe,
-                        particle.position,
-                        *unsafe { directions.get_unchecked(i) },
-                        mass,
-                    );
-                }
-                Some(*particle)
-            })
-            .collect()
+    };
+    snapshot::save_snapshot(&reference, &output)?;
+    println!("Saved reference fields to {}", output.display());
+
+    let Some(path) = args.compare else {
+        return Ok(());
+    };
+    let field = snapshot::load_snapshot(&path)
+        .map_err(|e| format!("Failed to load snapshot `{}`: {e}", path.display()))?;
+    if (field.width, field.height) != (reference.width, reference.height) {
+        return Err(format!(
+            "Snapshot `{}` is {}×{}, the scene {}×{}",
+            path.display(),
+            field.width,
+            field.height,
+            reference.width,
+            reference.height
+        )
+        .into());
+    }
+    if field.channels() != reference.channels() {
+        return Err(format!("Snapshot `{}` has other channels", path.display()).into());
+    }
+    for (index, channel) in reference.channels().iter().enumerate() {
+        let comparison = reference::compare(&field, &reference, index);
+        println!(
+            "Channel {}: scale {:.6e}, relative error {:.2}%, mean angle {:.2}°",
+            channel.name,
+            comparison.scale,
+            comparison.relative_error * 100.0,
+            comparison.mean_angle.to_degrees()
+        );
     }
+    Ok(())
+}
+
+/// Name of the Poisson solution written in the scene's output folder by default.
+const POISSON: &str = "poisson.snap";
+
+fn solve(args: SolveArgs) -> Result {
+    let Scene {
+        mut universe,
+        settings,
+        ..
+    } = load_scene(&args.scene)?;
+    println!("Solving Poisson's equation");
+    let convergences = poisson::solve_fields(&mut universe, &args.parameters);
+    for (channel, convergence) in universe.channels().iter().zip(&convergences) {
+        println!(
+            "Channel {}: {} cycles, relative residual {:.3e}",
+            channel.name, convergence.cycles, convergence.residual
+        );
+    }
+    let output = match args.output {
+        Some(output) => output,
+        None => {
+            std::fs::create_dir_all(&settings.output)?;
+            settings.output.join(POISSON)
+        }
+    };
+    snapshot::save_snapshot(&universe, &output)?;
+    println!("Saved fields to {}", output.display());
+    Ok(())
 }
 
-#[inline]
-fn process_sub_graviton(
-    universe: &mut Universe,
-    position: Point,
-    dir_sub_graviton: Point,
-    mass: f64,
-) {
-    let mut position = position;
-    let mut dir_sub_graviton = dir_sub_graviton * SUB_GRAVITON.step_size;
-    for _age in 0..SUB_GRAVITON.life_span {
-        if !position.is_inside(universe) {
-            return;
-        }
-        let element = universe.get_from_point_mut(position).unwrap();
-        element.mass.field -= dir_sub_graviton * mass;
-        // Advance sub-graviton's position
-        (position, dir_sub_graviton) = universe.move_in_universe(position, dir_sub_graviton);
+/// Arrays saved in place of a file: a folder without extension, or an archive.
+fn array_path(path: &Path, format: numpy::ArrayFormat) -> PathBuf {
+    match format {
+        numpy::ArrayFormat::Npy => path.with_extension(""),
+        numpy::ArrayFormat::Npz => path.with_extension("npz"),
+    }
+}
+
+fn export(args: ExportArgs) -> Result {
+    let universe = snapshot::load_snapshot(&args.snapshot)
+        .map_err(|e| format!("Failed to load snapshot `{}`: {e}", args.snapshot.display()))?;
+    let output = args
+        .output
+        .unwrap_or_else(|| array_path(&args.snapshot, args.format));
+    numpy::save_arrays(&universe, &output, args.format)?;
+    println!("Saved arrays to {}", output.display());
+    Ok(())
+}
+
+fn export_vtk(args: VtkArgs) -> Result {
+    let universe = snapshot::load_snapshot(&args.snapshot)
+        .map_err(|e| format!("Failed to load snapshot `{}`: {e}", args.snapshot.display()))?;
+    let base = args
+        .output
+        .unwrap_or_else(|| args.snapshot.with_extension(""));
+    let image = base.with_extension(args.format.image_extension());
+    vtk::save_image(&universe, &image, args.format)?;
+    println!("Saved fields to {}", image.display());
+    let mut portals = base.into_os_string();
+    portals.push("_portals");
+    let portals = PathBuf::from(portals).with_extension(args.format.polydata_extension());
+    vtk::save_portals(&universe, &portals, args.format)?;
+    println!("Saved portals to {}", portals.display());
+    Ok(())
+}
+
+fn inspect(path: &Path) -> Result {
+    let Scene {
+        universe,
+        bodies,
+        settings,
+    } = load_scene(path)?;
+    println!(
+        "Universe: {}×{}, {} elements in blocks of {}",
+        universe.width,
+        universe.height,
+        universe.element_count(),
+        universe.block_size()
+    );
+
+    // Gravitons, sub-gravitons per step and deposits, over all channels.
+    let (mut gravitons, mut sub_gravitons, mut deposits) = (0u64, 0u64, 0u64);
+    for (index, channel) in universe.channels().iter().enumerate() {
+        let mut cells = 0u64;
+        let mut total = 0.0;
+        universe.for_each_element(|origin, size, element| {
+            let value = element.property(index).value;
+            if value.is_normal() {
+                cells += 1;
+                total += value * universe.area(origin, size);
+            }
+        });
+        let ParticleParameters {
+            quantity,
+            life_span,
+            ..
+        } = channel.graviton.unwrap_or(settings.graviton);
+        let sub = channel.sub_graviton.unwrap_or(settings.sub_graviton);
+        println!(
+            "Channel {} (coupling {}): {cells} elements, {total} in total",
+            channel.name, channel.coupling
+        );
+        println!(
+            "  Gravitons: {} ({quantity} per source element), living {life_span} steps",
+            cells * quantity as u64
+        );
+        println!(
+            "  Sub-gravitons: {} per graviton, living {} steps",
+            sub.quantity, sub.life_span
+        );
+        let channel_sub_gravitons = cells * quantity as u64 * sub.quantity as u64;
+        gravitons += cells * quantity as u64;
+        sub_gravitons += channel_sub_gravitons;
+        deposits += channel_sub_gravitons * life_span as u64 * sub.life_span as u64;
+    }
+
+    let Emission { directions, seed } = settings.emission;
+    let name = match directions {
+        Directions::Even => "evenly spaced",
+        Directions::GoldenAngle => "golden angle",
+        Directions::Halton => "Halton",
+        Directions::Random => "random",
+    };
+    match (directions, seed) {
+        (Directions::Random, seed) => {
+            println!("Emission: {name} angles, seed {}", seed.unwrap_or(0))
+        }
+        (_, Some(seed)) => println!("Emission: {name} angles, turned randomly, seed {seed}"),
+        (_, None) => println!("Emission: {name} angles"),
+    }
+
+    println!("Portal sets: {}", universe.portals().len());
+    for (i, PortalSet { a, b }) in universe.portals().iter().enumerate() {
+        for (name, portal) in [("a", a), ("b", b)] {
+            let delta = portal.point_b - portal.point_a;
+            println!(
+                "  [{i}].{name}: ({}, {}) → ({}, {}), size {:.3}, angle {:.2}°",
+                portal.point_a.x,
+                portal.point_a.y,
+                portal.point_b.x,
+                portal.point_b.y,
+                portal.size(),
+                delta.y.atan2(delta.x).to_degrees(),
+            );
+        }
+    }
+
+    if let Some(dynamics) = settings.dynamics {
+        println!(
+            "Dynamic run: {} steps, strength {}, {} bodies",
+            dynamics.steps,
+            dynamics.strength,
+            bodies.len()
+        );
+        for (i, body) in bodies.iter().enumerate() {
+            println!(
+                "  [{i}]: {} at {}, moving by {} per step",
+                universe.channels()[body.channel].name,
+                body.position,
+                body.velocity
+            );
+        }
+        println!("  Sources and bodies emit gravitons again at every step");
     }
+    println!("Gravitons: {gravitons}");
+    println!("Sub-gravitons: up to {sub_gravitons} per step");
+    println!("Field deposits: up to {deposits} in total");
+    Ok(())
 }
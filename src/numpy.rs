@@ -0,0 +1,312 @@
+//! Raw fields as NumPy arrays, for analysis outside of the simulator.
+//!
+//! Every channel gives three `float64` arrays of shape `(height, width)`, one value per cell:
+//! `<channel>_value`, `<channel>_field_x` and `<channel>_field_y`. They're written as
+//! separate `.npy` files, or bundled in an uncompressed `.npz` archive, which `numpy.load`
+//! reads as a dictionary.
+
+use crate::types::Universe;
+
+use std::{
+    fmt::{Display, Formatter, Result as FmtResult},
+    fs::{self, File},
+    io::{self, BufWriter, Write},
+    path::Path,
+    str::FromStr,
+};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ArrayFormat {
+    /// A folder of `.npy` files.
+    Npy,
+    /// A single `.npz` file.
+    #[default]
+    Npz,
+}
+
+impl ArrayFormat {
+    pub fn extension(self) -> &'static str {
+        match self {
+            ArrayFormat::Npy => "npy",
+            ArrayFormat::Npz => "npz",
+        }
+    }
+}
+
+impl FromStr for ArrayFormat {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "npy" => Ok(ArrayFormat::Npy),
+            "npz" => Ok(ArrayFormat::Npz),
+            _ => Err(format!(
+                "unknown array format `{s}`, expected `npy` or `npz`"
+            )),
+        }
+    }
+}
+
+impl Display for ArrayFormat {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        f.write_str(self.extension())
+    }
+}
+
+/// The arrays of every channel, by name, row by row.
+pub fn arrays(universe: &Universe) -> Vec<(String, Vec<f64>)> {
+    let cells = universe.width as usize * universe.height as usize;
+    let mut arrays = Vec::with_capacity(universe.channels().len() * 3);
+    for (index, channel) in universe.channels().iter().enumerate() {
+        let mut value = Vec::with_capacity(cells);
+        let mut field_x = Vec::with_capacity(cells);
+        let mut field_y = Vec::with_capacity(cells);
+        for y in 0..universe.height {
+            for x in 0..universe.width {
+                let property = universe[(x, y)].element().unwrap().property(index);
+                value.push(property.value);
+                field_x.push(property.field.x);
+                field_y.push(property.field.y);
+            }
+        }
+        arrays.push((format!("{}_value", channel.name), value));
+        arrays.push((format!("{}_field_x", channel.name), field_x));
+        arrays.push((format!("{}_field_y", channel.name), field_y));
+    }
+    arrays
+}
+
+/// Saves the arrays as `path`, a folder for [`ArrayFormat::Npy`], a file for
+/// [`ArrayFormat::Npz`].
+pub fn save_arrays(
+    universe: &Universe,
+    path: impl AsRef<Path>,
+    format: ArrayFormat,
+) -> io::Result<()> {
+    match format {
+        ArrayFormat::Npy => save_npy(universe, path),
+        ArrayFormat::Npz => save_npz(universe, path),
+    }
+}
+
+/// Writes one `<array>.npy` file per array in `folder`, creating it if needed.
+pub fn save_npy(universe: &Universe, folder: impl AsRef<Path>) -> io::Result<()> {
+    let folder = folder.as_ref();
+    fs::create_dir_all(folder)?;
+    let shape = (universe.height as usize, universe.width as usize);
+    for (name, data) in arrays(universe) {
+        let mut writer = BufWriter::new(File::create(folder.join(name + ".npy"))?);
+        write_npy(&mut writer, shape, &data)?;
+        writer.flush()?;
+    }
+    Ok(())
+}
+
+pub fn save_npz(universe: &Universe, path: impl AsRef<Path>) -> io::Result<()> {
+    let shape = (universe.height as usize, universe.width as usize);
+    let files = arrays(universe)
+        .into_iter()
+        .map(|(name, data)| {
+            let mut bytes = Vec::new();
+            write_npy(&mut bytes, shape, &data)?;
+            Ok((name + ".npy", bytes))
+        })
+        .collect::<io::Result<Vec<_>>>()?;
+    let mut writer = BufWriter::new(File::create(path)?);
+    write_zip(&mut writer, &files)?;
+    writer.flush()
+}
+
+/// Writes a 2D `float64` array in the `.npy` format, version 1.0.
+///
+/// https://numpy.org/doc/stable/reference/generated/numpy.lib.format.html
+pub fn write_npy(
+    writer: &mut impl Write,
+    (rows, columns): (usize, usize),
+    data: &[f64],
+) -> io::Result<()> {
+    assert_eq!(
+        rows * columns,
+        data.len(),
+        "array shape doesn't match its data"
+    );
+    const MAGIC: &[u8] = b"\x93NUMPY\x01\x00";
+    let mut header =
+        format!("{{'descr': '<f8', 'fortran_order': False, 'shape': ({rows}, {columns}), }}");
+    // The data is aligned on 64 bytes, after the magic, the header length and the header.
+    let unpadded = MAGIC.len() + 2 + header.len() + 1;
+    header.extend(std::iter::repeat_n(
+        ' ',
+        unpadded.next_multiple_of(64) - unpadded,
+    ));
+    header.push('\n');
+
+    writer.write_all(MAGIC)?;
+    writer.write_all(&(header.len() as u16).to_le_bytes())?;
+    writer.write_all(header.as_bytes())?;
+    for value in data {
+        writer.write_all(&value.to_le_bytes())?;
+    }
+    Ok(())
+}
+
+/// Writes an uncompressed zip archive of `(name, content)` files.
+///
+/// https://pkware.cachefly.net/webdocs/casestudies/APPNOTE.TXT
+fn write_zip(writer: &mut impl Write, files: &[(String, Vec<u8>)]) -> io::Result<()> {
+    let too_large = || io::Error::new(io::ErrorKind::InvalidInput, "archive over 4 GiB");
+    const VERSION: u16 = 20;
+    // (crc, size, offset) of each file, for the central directory.
+    let mut entries = Vec::with_capacity(files.len());
+    let mut offset = 0u32;
+    for (name, content) in files {
+        let crc = crc32(content);
+        let size = u32::try_from(content.len()).map_err(|_| too_large())?;
+        let mut header = Vec::with_capacity(30 + name.len());
+        header.extend(0x04034b50u32.to_le_bytes());
+        header.extend(VERSION.to_le_bytes());
+        // Flags, method (stored), time and date.
+        header.extend([0; 8]);
+        header.extend(crc.to_le_bytes());
+        header.extend(size.to_le_bytes());
+        header.extend(size.to_le_bytes());
+        header.extend((name.len() as u16).to_le_bytes());
+        header.extend(0u16.to_le_bytes());
+        header.extend(name.as_bytes());
+        writer.write_all(&header)?;
+        writer.write_all(content)?;
+        entries.push((crc, size, offset));
+        offset = (offset.checked_add(header.len() as u32))
+            .and_then(|offset| offset.checked_add(size))
+            .ok_or_else(too_large)?;
+    }
+
+    let mut directory = Vec::new();
+    for ((name, _), (crc, size, offset)) in files.iter().zip(entries) {
+        directory.extend(0x02014b50u32.to_le_bytes());
+        directory.extend(VERSION.to_le_bytes());
+        directory.extend(VERSION.to_le_bytes());
+        directory.extend([0; 8]);
+        directory.extend(crc.to_le_bytes());
+        directory.extend(size.to_le_bytes());
+        directory.extend(size.to_le_bytes());
+        directory.extend((name.len() as u16).to_le_bytes());
+        // Extra field and comment lengths, disk number, attributes.
+        directory.extend([0; 12]);
+        directory.extend(offset.to_le_bytes());
+        directory.extend(name.as_bytes());
+    }
+    writer.write_all(&directory)?;
+
+    let mut end = Vec::with_capacity(22);
+    end.extend(0x06054b50u32.to_le_bytes());
+    end.extend([0; 4]);
+    end.extend((files.len() as u16).to_le_bytes());
+    end.extend((files.len() as u16).to_le_bytes());
+    end.extend((directory.len() as u32).to_le_bytes());
+    end.extend(offset.to_le_bytes());
+    end.extend(0u16.to_le_bytes());
+    writer.write_all(&end)
+}
+
+/// CRC-32 of zip files, IEEE polynomial.
+fn crc32(bytes: &[u8]) -> u32 {
+    const TABLE: [u32; 256] = {
+        let mut table = [0; 256];
+        let mut i = 0;
+        while i < 256 {
+            let mut crc = i as u32;
+            let mut bit = 0;
+            while bit < 8 {
+                crc = if crc & 1 == 1 {
+                    0xEDB88320 ^ (crc >> 1)
+                } else {
+                    crc >> 1
+                };
+                bit += 1;
+            }
+            table[i] = crc;
+            i += 1;
+        }
+        table
+    };
+    !bytes.iter().fold(!0u32, |crc, &byte| {
+        TABLE[((crc ^ byte as u32) & 0xFF) as usize] ^ (crc >> 8)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn u16_at(bytes: &[u8], at: usize) -> u16 {
+        u16::from_le_bytes(bytes[at..at + 2].try_into().unwrap())
+    }
+    fn u32_at(bytes: &[u8], at: usize) -> u32 {
+        u32::from_le_bytes(bytes[at..at + 4].try_into().unwrap())
+    }
+
+    #[test]
+    fn crc32_matches_the_check_value() {
+        assert_eq!(crc32(b""), 0);
+        assert_eq!(crc32(b"123456789"), 0xCBF43926);
+    }
+
+    #[test]
+    fn npy_arrays_read_back() {
+        let data: Vec<f64> = (0..6).map(|i| i as f64 * -1.5).collect();
+        let mut bytes = Vec::new();
+        write_npy(&mut bytes, (2, 3), &data).unwrap();
+
+        assert_eq!(&bytes[..8], b"\x93NUMPY\x01\x00");
+        let start = 10 + u16_at(&bytes, 8) as usize;
+        assert_eq!(start % 64, 0);
+        let header = std::str::from_utf8(&bytes[10..start]).unwrap();
+        assert!(header.ends_with('\n'), "{header:?}");
+        assert_eq!(
+            header.trim_end(),
+            "{'descr': '<f8', 'fortran_order': False, 'shape': (2, 3), }"
+        );
+        let read: Vec<f64> = bytes[start..]
+            .chunks_exact(8)
+            .map(|value| f64::from_le_bytes(value.try_into().unwrap()))
+            .collect();
+        assert_eq!(read, data);
+    }
+
+    #[test]
+    fn zip_entries_read_back() {
+        let files = vec![
+            ("a.npy".to_string(), b"first".to_vec()),
+            ("bb.npy".to_string(), Vec::new()),
+            ("c.npy".to_string(), (0..=255).collect()),
+        ];
+        let mut zip = Vec::new();
+        write_zip(&mut zip, &files).unwrap();
+
+        // The end record gives the central directory, whose entries give the local files.
+        let end = zip.len() - 22;
+        assert_eq!(u32_at(&zip, end), 0x06054b50);
+        assert_eq!(u16_at(&zip, end + 10) as usize, files.len());
+        let mut entry = u32_at(&zip, end + 16) as usize;
+        assert_eq!(entry + u32_at(&zip, end + 12) as usize, end);
+        for (name, content) in &files {
+            assert_eq!(u32_at(&zip, entry), 0x02014b50);
+            let (crc, size) = (u32_at(&zip, entry + 16), u32_at(&zip, entry + 20));
+            assert_eq!((crc, size as usize), (crc32(content), content.len()));
+            let name_length = u16_at(&zip, entry + 28) as usize;
+            assert_eq!(&zip[entry + 46..entry + 46 + name_length], name.as_bytes());
+
+            let local = u32_at(&zip, entry + 42) as usize;
+            assert_eq!(u32_at(&zip, local), 0x04034b50);
+            assert_eq!(
+                (u32_at(&zip, local + 14), u32_at(&zip, local + 18)),
+                (crc, size)
+            );
+            let start = local + 30 + name_length + u16_at(&zip, local + 28) as usize;
+            assert_eq!(&zip[local + 30..local + 30 + name_length], name.as_bytes());
+            assert_eq!(&zip[start..start + content.len()], &content[..]);
+            entry += 46 + name_length;
+        }
+        assert_eq!(entry, end);
+    }
+}
@@ -0,0 +1,455 @@
+//! Fields from a potential, solving Poisson's equation on the grid instead of emitting
+//! particles.
+//!
+//! The Laplacian is the usual 5-point stencil, except that the neighbour of a cell is found
+//! by moving from its center with [`Universe::move_in_universe`]: cells on either side of a
+//! portal are neighbours of the matching cells of its partner. The potential is zero outside
+//! of the universe.
+//!
+//! Solving `∇²φ = 2πρ` gives potentials whose gradient is, away from the edges, the field
+//! of [`reference`](crate::reference): `1/r` for point sources. Each channel's field is its
+//! coupling times `∇φ`.
+//!
+//! The system is solved by BiCGSTAB (the stitched stencil needn't be symmetric),
+//! preconditioned by multigrid V-cycles over levels built by aggregating 2×2 blocks of
+//! unknowns, whose operators are the Galerkin products `Pᵀ A P`.
+
+use crate::types::{Point, Universe};
+
+use core::f64::consts::TAU;
+use std::collections::HashMap;
+
+/// When to stop iterating.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SolverParameters {
+    /// Residual to reach, relative to the right-hand side.
+    pub tolerance: f64,
+    pub max_cycles: u32,
+}
+
+impl Default for SolverParameters {
+    fn default() -> Self {
+        SolverParameters {
+            tolerance: 1e-6,
+            max_cycles: 100,
+        }
+    }
+}
+
+/// How far a solve went.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Convergence {
+    /// BiCGSTAB iterations, of two V-cycles each.
+    pub cycles: u32,
+    /// Final residual, relative to the right-hand side.
+    pub residual: f64,
+}
+
+/// Gauss-Seidel sweeps before and after each coarse correction.
+const SMOOTHING: usize = 2;
+/// Levels with fewer unknowns than this are solved by smoothing alone.
+const COARSEST: usize = 64;
+const COARSEST_SWEEPS: usize = 200;
+/// Coarse corrections from piecewise constant aggregates are too small, they get scaled.
+const CORRECTION: f64 = 1.4;
+
+/// A sparse matrix with its diagonal apart, and how its unknowns aggregate on the next level.
+#[derive(Debug, Clone, Default)]
+struct Level {
+    diagonal: Vec<f64>,
+    /// Off-diagonal entries of each row, rows `offsets[i]..offsets[i + 1]`.
+    offsets: Vec<usize>,
+    columns: Vec<usize>,
+    values: Vec<f64>,
+    /// Cell, or block of cells, of each unknown.
+    coordinates: Vec<(u32, u32)>,
+    /// Unknown of the next level each unknown belongs to, empty on the coarsest level.
+    aggregates: Vec<usize>,
+}
+
+impl Level {
+    /// Builds a level from `(row, column, value)` entries, summing duplicates.
+    fn new(coordinates: Vec<(u32, u32)>, mut entries: Vec<(usize, usize, f64)>) -> Level {
+        let n = coordinates.len();
+        entries.sort_unstable_by_key(|&(row, column, _)| (row, column));
+        let mut level = Level {
+            diagonal: vec![0.0; n],
+            offsets: Vec::with_capacity(n + 1),
+            coordinates,
+            ..Default::default()
+        };
+        let mut entries = entries.into_iter().peekable();
+        for row in 0..n {
+            level.offsets.push(level.columns.len());
+            while let Some((_, column, mut value)) = entries.next_if(|e| e.0 == row) {
+                while let Some((.., more)) = entries.next_if(|e| e.0 == row && e.1 == column) {
+                    value += more;
+                }
+                if column == row {
+                    level.diagonal[row] += value;
+                } else {
+                    level.columns.push(column);
+                    level.values.push(value);
+                }
+            }
+        }
+        level.offsets.push(level.columns.len());
+        level
+    }
+
+    fn len(&self) -> usize {
+        self.diagonal.len()
+    }
+
+    fn row(&self, i: usize) -> impl Iterator<Item = (usize, f64)> + '_ {
+        let range = self.offsets[i]..self.offsets[i + 1];
+        self.columns[range.clone()]
+            .iter()
+            .copied()
+            .zip(self.values[range].iter().copied())
+    }
+
+    fn gauss_seidel(&self, x: &mut [f64], b: &[f64], sweeps: usize) {
+        for _ in 0..sweeps {
+            for i in 0..self.len() {
+                let sum: f64 = self.row(i).map(|(j, v)| v * x[j]).sum();
+                x[i] = (b[i] - sum) / self.diagonal[i];
+            }
+        }
+    }
+
+    fn multiply(&self, x: &[f64]) -> Vec<f64> {
+        (0..self.len())
+            .map(|i| self.diagonal[i] * x[i] + self.row(i).map(|(j, v)| v * x[j]).sum::<f64>())
+            .collect()
+    }
+
+    fn residual(&self, x: &[f64], b: &[f64]) -> Vec<f64> {
+        (0..self.len())
+            .map(|i| {
+                b[i] - self.diagonal[i] * x[i] - self.row(i).map(|(j, v)| v * x[j]).sum::<f64>()
+            })
+            .collect()
+    }
+
+    /// Aggregates 2×2 blocks of unknowns, returning the next level, if any smaller.
+    fn coarsen(&mut self) -> Option<Level> {
+        let mut indices = HashMap::new();
+        let mut coordinates = Vec::new();
+        self.aggregates = (self.coordinates.iter())
+            .map(|&(x, y)| {
+                *indices.entry((x / 2, y / 2)).or_insert_with(|| {
+                    coordinates.push((x / 2, y / 2));
+                    coordinates.len() - 1
+                })
+            })
+            .collect();
+        if coordinates.len() == self.len() {
+            self.aggregates.clear();
+            return None;
+        }
+        let mut entries = Vec::with_capacity(self.len() + self.columns.len());
+        for i in 0..self.len() {
+            let row = self.aggregates[i];
+            entries.push((row, row, self.diagonal[i]));
+            entries.extend(self.row(i).map(|(j, v)| (row, self.aggregates[j], v)));
+        }
+        Some(Level::new(coordinates, entries))
+    }
+}
+
+/// A multigrid hierarchy for the Laplacian of a universe, on its cells.
+#[derive(Debug, Clone)]
+pub struct Solver {
+    width: u32,
+    levels: Vec<Level>,
+    /// Right, left, lower and upper neighbours of each cell, if inside the universe.
+    neighbours: Vec<[Option<usize>; 4]>,
+}
+
+const DIRECTIONS: [Point; 4] = [
+    Point { x: 1.0, y: 0.0 },
+    Point { x: -1.0, y: 0.0 },
+    Point { x: 0.0, y: 1.0 },
+    Point { x: 0.0, y: -1.0 },
+];
+
+impl Solver {
+    pub fn new(universe: &Universe) -> Solver {
+        let (width, height) = (universe.width, universe.height);
+        let cells = width as usize * height as usize;
+        let mut coordinates = Vec::with_capacity(cells);
+        let mut neighbours = Vec::with_capacity(cells);
+        for y in 0..height {
+            for x in 0..width {
+                let center = Point {
+                    x: x as f64 + 0.5,
+                    y: y as f64 + 0.5,
+                };
+                coordinates.push((x, y));
+                neighbours.push(DIRECTIONS.map(|direction| {
+                    let (position, _) = universe.move_in_universe(center, direction);
+                    position
+                        .is_inside(universe)
+                        .then(|| position.x as usize + position.y as usize * width as usize)
+                }));
+            }
+        }
+        // -∇², positive definite, as the potential is zero out of the universe.
+        let mut entries = Vec::with_capacity(cells * 5);
+        for (i, cell) in neighbours.iter().enumerate() {
+            entries.push((i, i, 4.0));
+            entries.extend(cell.iter().flatten().map(|&j| (i, j, -1.0)));
+        }
+
+        let mut levels = vec![Level::new(coordinates, entries)];
+        while levels.last().unwrap().len() > COARSEST {
+            match levels.last_mut().unwrap().coarsen() {
+                Some(level) => levels.push(level),
+                None => break,
+            }
+        }
+        Solver {
+            width,
+            levels,
+            neighbours,
+        }
+    }
+
+    /// Solves `∇²φ = rhs`, with one value per cell, row by row.
+    pub fn solve(&self, rhs: &[f64], parameters: &SolverParameters) -> (Vec<f64>, Convergence) {
+        let a = &self.levels[0];
+        let b: Vec<f64> = rhs.iter().map(|v| -v).collect();
+        let norm = dot(&b, &b).sqrt();
+        let mut x = vec![0.0; b.len()];
+        let mut convergence = Convergence::default();
+        if norm == 0.0 {
+            return (x, convergence);
+        }
+        let precondition = |r: &[f64]| {
+            let mut z = vec![0.0; r.len()];
+            self.v_cycle(0, &mut z, r);
+            z
+        };
+
+        let mut r = b;
+        let r_0 = r.clone();
+        let (mut rho, mut alpha, mut omega) = (1.0, 1.0, 1.0);
+        let mut v = vec![0.0; r.len()];
+        let mut p = vec![0.0; r.len()];
+        loop {
+            convergence.residual = dot(&r, &r).sqrt() / norm;
+            if convergence.residual <= parameters.tolerance
+                || convergence.cycles >= parameters.max_cycles
+            {
+                return (x, convergence);
+            }
+            convergence.cycles += 1;
+
+            let rho_next = dot(&r_0, &r);
+            let beta = (rho_next / rho) * (alpha / omega);
+            rho = rho_next;
+            for i in 0..p.len() {
+                p[i] = r[i] + beta * (p[i] - omega * v[i]);
+            }
+            let y = precondition(&p);
+            v = a.multiply(&y);
+            alpha = rho / dot(&r_0, &v);
+            let s: Vec<f64> = (0..r.len()).map(|i| r[i] - alpha * v[i]).collect();
+            let z = precondition(&s);
+            let t = a.multiply(&z);
+            omega = dot(&t, &s) / dot(&t, &t);
+            for i in 0..x.len() {
+                x[i] += alpha * y[i] + omega * z[i];
+                r[i] = s[i] - omega * t[i];
+            }
+            // Breakdown, from which restarting wouldn't help much at these sizes.
+            if !(rho.is_normal() && omega.is_normal()) {
+                convergence.residual = dot(&r, &r).sqrt() / norm;
+                return (x, convergence);
+            }
+        }
+    }
+
+    fn v_cycle(&self, depth: usize, x: &mut [f64], b: &[f64]) {
+        let level = &self.levels[depth];
+        let Some(coarse) = self.levels.get(depth + 1) else {
+            level.gauss_seidel(x, b, COARSEST_SWEEPS);
+            return;
+        };
+        level.gauss_seidel(x, b, SMOOTHING);
+        let mut coarse_b = vec![0.0; coarse.len()];
+        for (i, r) in level.residual(x, b).into_iter().enumerate() {
+            coarse_b[level.aggregates[i]] += r;
+        }
+        let mut coarse_x = vec![0.0; coarse.len()];
+        self.v_cycle(depth + 1, &mut coarse_x, &coarse_b);
+        for (i, x) in x.iter_mut().enumerate() {
+            *x += CORRECTION * coarse_x[level.aggregates[i]];
+        }
+        level.gauss_seidel(x, b, SMOOTHING);
+    }
+
+    /// Gradient of a potential, by central differences across the (stitched) neighbours.
+    pub fn gradient(&self, potential: &[f64]) -> Vec<Point> {
+        let value = |neighbour: Option<usize>| neighbour.map_or(0.0, |j| potential[j]);
+        (self.neighbours.iter())
+            .map(|[right, left, down, up]| Point {
+                x: (value(*right) - value(*left)) / 2.0,
+                y: (value(*down) - value(*up)) / 2.0,
+            })
+            .collect()
+    }
+
+    fn cell(&self, (x, y): (u32, u32)) -> usize {
+        x as usize + y as usize * self.width as usize
+    }
+}
+
+fn dot(a: &[f64], b: &[f64]) -> f64 {
+    a.iter().zip(b).map(|(a, b)| a * b).sum()
+}
+
+/// Replaces the field of every channel by its coupling times `∇φ`, where
+/// `∇²φ = 2πρ`, returning how each solve converged.
+///
+/// Elements larger than a cell get the mean field of their cells.
+pub fn solve_fields(universe: &mut Universe, parameters: &SolverParameters) -> Vec<Convergence> {
+    let solver = Solver::new(universe);
+    let cells = solver.neighbours.len();
+    let dimensions = (universe.width, universe.height);
+    let mut convergences = Vec::with_capacity(universe.channels().len());
+    for channel in 0..universe.channels().len() {
+        let mut rhs = vec![0.0; cells];
+        universe.for_each_element(|origin, size, element| {
+            let density = element.property(channel).value;
+            for_each_cell(dimensions, origin, size, |cell| {
+                rhs[solver.cell(cell)] = TAU * density;
+            });
+        });
+        let (potential, convergence) = solver.solve(&rhs, parameters);
+        convergences.push(convergence);
+        let gradient = solver.gradient(&potential);
+        let coupling = universe.channels()[channel].coupling;
+        universe.for_each_element_mut(|origin, size, element| {
+            let mut sum = Point::default();
+            let mut count = 0;
+            for_each_cell(dimensions, origin, size, |cell| {
+                sum += gradient[solver.cell(cell)];
+                count += 1;
+            });
+            // Quadrants beyond the edge of the universe cover no cell, and keep no field.
+            element.property_mut(channel).field = match count {
+                0 => Point::default(),
+                count => sum * (coupling / count as f64),
+            };
+        });
+    }
+    convergences
+}
+
+/// Calls `f` with the cells of a region inside the universe.
+fn for_each_cell(
+    (width, height): (u32, u32),
+    (x, y): (u32, u32),
+    size: u32,
+    mut f: impl FnMut((u32, u32)),
+) {
+    for yi in y..(y + size).min(height) {
+        for xi in x..(x + size).min(width) {
+            f((xi, yi));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        reference::reference_field,
+        types::{Channel, Portal, PortalSet},
+    };
+
+    #[test]
+    fn blocks_sticking_out_of_the_universe_keep_finite_fields() {
+        let mut universe = Universe::adaptive(50, 50, 16, vec![Channel::mass()]);
+        universe.refine(|_, _| true);
+        universe.for_each_element(|(x, y), size, _| {
+            assert!(size > 1 || (x < 50 && y < 50), "cell ({x}, {y}) refined");
+        });
+        universe[(48, 48)].element_mut().unwrap().properties[0].value = 1.0;
+
+        solve_fields(&mut universe, &SolverParameters::default());
+        universe.for_each_element(|origin, _, element| {
+            let field = element.property(0).field;
+            assert!(field.x.is_finite() && field.y.is_finite(), "{origin:?}");
+        });
+        let field = universe[(45, 48)].element().unwrap().property(0).field;
+        assert!(field.x > 0.0, "{field}");
+    }
+
+    /// The field of cell `(x, y)` of channel 0.
+    fn field(universe: &Universe, cell: (u32, u32)) -> Point {
+        universe[cell].element().unwrap().property(0).field
+    }
+
+    #[test]
+    fn a_lone_source_matches_the_reference_away_from_the_edges() {
+        let mut universe = Universe::new(81, 81, vec![Channel::mass()]);
+        universe[(40, 40)].element_mut().unwrap().properties[0].value = 1.0;
+        let reference = reference_field(&universe, 0);
+        solve_fields(&mut universe, &SolverParameters::default());
+        // Far enough from the source for the half cell between its positions in either, and
+        // from the zero potential of the edges.
+        let mut worst = 0f64;
+        for y in 20..=60 {
+            for x in 20..=60 {
+                let distance = (x as f64 - 40.0).hypot(y as f64 - 40.0);
+                if (8.0..=20.0).contains(&distance) {
+                    let (solved, reference) = (field(&universe, (x, y)), field(&reference, (x, y)));
+                    worst = worst.max((solved - reference).magnitude() / reference.magnitude());
+                }
+            }
+        }
+        assert!(worst < 0.12, "relative error up to {worst}");
+    }
+
+    #[test]
+    fn potentials_are_continuous_across_portals() {
+        let p = |x, y| Point { x, y };
+        let mut universe = Universe::new(40, 70, vec![Channel::mass()]);
+        // Crossing y = 20 downwards leads below y = 50, and back.
+        universe.add_portal_set(PortalSet::new(
+            Portal::new(p(10.0, 20.0), p(30.0, 20.0)),
+            Portal::new(p(10.0, 50.0), p(30.0, 50.0)),
+        ));
+        let solver = Solver::new(&universe);
+        let mut rhs = vec![0.0; 40 * 70];
+        rhs[solver.cell((20, 12))] = TAU;
+        let (potential, _) = solver.solve(&rhs, &SolverParameters::default());
+        let gradient = solver.gradient(&potential);
+        let at = |x, y| potential[solver.cell((x, y))];
+        let field_at = |x, y| gradient[solver.cell((x, y))];
+        for x in 12..28 {
+            // Rising steadily away from the source, the cells across the portal included.
+            let steps = [
+                at(x, 19) - at(x, 18),
+                at(x, 50) - at(x, 19),
+                at(x, 51) - at(x, 50),
+            ];
+            assert!(
+                steps[0] >= steps[1] && steps[1] >= steps[2] && steps[2] > 0.0,
+                "{x}: {steps:?}"
+            );
+            // Unlike the cell beyond the portal, cut off from the source.
+            assert!(at(x, 20) - at(x, 19) > 5.0 * steps[1], "{x}");
+
+            let changes = [
+                (field_at(x, 19) - field_at(x, 18)).magnitude(),
+                (field_at(x, 50) - field_at(x, 19)).magnitude(),
+                (field_at(x, 51) - field_at(x, 50)).magnitude(),
+            ];
+            assert!(changes[1] <= changes[0].max(changes[2]), "{x}: {changes:?}");
+        }
+    }
+}
@@ -0,0 +1,36 @@
+//! Small deterministic random numbers, so that seeded runs can be reproduced exactly.
+
+/// The SplitMix64 generator: tiny, fast, and good enough for picking directions.
+///
+/// https://prng.di.unimi.it/splitmix64.c
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SplitMix64 {
+    state: u64,
+}
+
+impl SplitMix64 {
+    pub const fn new(seed: u64) -> Self {
+        SplitMix64 { state: seed }
+    }
+
+    /// A generator of its own for the stream identified by `keys`, independent of the
+    /// order in which streams are drawn, unlike successive numbers of one generator.
+    pub fn stream(seed: u64, keys: &[u64]) -> Self {
+        keys.iter().fold(SplitMix64::new(seed), |mut rng, &key| {
+            SplitMix64::new(rng.next_u64() ^ key)
+        })
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E3779B97F4A7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform in `[0, 1)`, with the 53 bits of precision of an `f64`.
+    pub fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 * (1.0 / (1u64 << 53) as f64)
+    }
+}
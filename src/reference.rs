@@ -0,0 +1,334 @@
+//! Reference fields, computed directly instead of by gravitons, to measure the error of the
+//! particle method.
+//!
+//! Every source contributes a 2D `1/r` field along the straight lines leaving it, and along
+//! the lines going through portals: seen through a chain of portals, a source acts as if it
+//! stood at its image by the teleports, for the points the chain actually reaches.
+
+use crate::types::{Point, Portal, Universe};
+
+use rayon::prelude::*;
+
+/// Identifies a portal: the index of its set, and `0` for `a` or `1` for `b`.
+type PortalId = (usize, usize);
+
+/// A teleport from `entry` to `exit`.
+#[derive(Debug, Clone, Copy)]
+struct Hop {
+    entry: (PortalId, Portal),
+    exit: (PortalId, Portal),
+}
+
+/// A source as seen through a chain of teleports.
+#[derive(Debug, Clone)]
+struct Image {
+    /// Position of the source, then of its image after each hop.
+    positions: Vec<Point>,
+    hops: Vec<Hop>,
+    value: f64,
+    channel: usize,
+}
+
+/// Computes the field of every channel, through up to `depth` portal traversals.
+///
+/// The result has the sources and structure of `universe`, with the exact field at the
+/// center of each element. The gravitons' field only matches it up to a factor, see
+/// [`compare`].
+pub fn reference_field(universe: &Universe, depth: u32) -> Universe {
+    let hops: Vec<Hop> = (universe.portals().iter().enumerate())
+        .flat_map(|(i, set)| {
+            let (a, b) = (((i, 0), set.a), ((i, 1), set.b));
+            [Hop { entry: a, exit: b }, Hop { entry: b, exit: a }]
+        })
+        .collect();
+    let portals: Vec<(PortalId, Portal)> = hops.iter().map(|hop| hop.entry).collect();
+
+    // Sources, where the gravitons would spawn.
+    let mut images = Vec::new();
+    universe.for_each_element(|(x, y), size, element| {
+        for (channel, property) in element.properties.iter().enumerate() {
+            if !property.value.is_normal() {
+                continue;
+            }
+            let offset = (size - 1) as f64 / 2.0;
+            let source = Image {
+                positions: vec![Point {
+                    x: x as f64 + offset,
+                    y: y as f64 + offset,
+                }],
+                hops: Vec::new(),
+                value: property.value * universe.area((x, y), size),
+                channel,
+            };
+            add_images(&mut images, source, &hops, depth);
+        }
+    });
+
+    let mut centers = Vec::with_capacity(universe.element_count());
+    universe.for_each_element(|(x, y), size, _| {
+        let half = size as f64 / 2.0;
+        centers.push(Point {
+            x: x as f64 + half,
+            y: y as f64 + half,
+        });
+    });
+    let couplings: Vec<f64> = universe.channels().iter().map(|c| c.coupling).collect();
+    let fields: Vec<Vec<Point>> = centers
+        .par_iter()
+        .map(|&point| {
+            let mut fields = vec![Point::default(); couplings.len()];
+            for image in &images {
+                let delta = point - image.position();
+                let distance_2 = delta.magnitude_2();
+                if distance_2 == 0.0 || !image.reaches(point, &portals) {
+                    continue;
+                }
+                fields[image.channel] +=
+                    delta * (couplings[image.channel] * image.value / distance_2);
+            }
+            fields
+        })
+        .collect();
+
+    let mut reference = universe.clone();
+    let mut fields = fields.into_iter();
+    reference.for_each_element_mut(|_, _, element| {
+        let field = fields.next().unwrap();
+        for (property, field) in element.properties.iter_mut().zip(field) {
+            property.field = field;
+        }
+    });
+    reference
+}
+
+/// Adds `image` and its images through up to `depth` more hops.
+fn add_images(images: &mut Vec<Image>, image: Image, hops: &[Hop], depth: u32) {
+    if depth > 0 {
+        for hop in hops {
+            // A line leaving a portal can't cross it again.
+            if image
+                .hops
+                .last()
+                .is_some_and(|last| last.exit.0 == hop.entry.0)
+            {
+                continue;
+            }
+            let (_, entry) = hop.entry;
+            let (_, exit) = hop.exit;
+            let mut next = image.clone();
+            next.positions
+                .push(exit.reverted_relative_position(entry.relative_position(image.position())));
+            next.hops.push(*hop);
+            add_images(images, next, hops, depth - 1);
+        }
+    }
+    images.push(image);
+}
+
+impl Image {
+    fn position(&self) -> Point {
+        *self.positions.last().unwrap()
+    }
+
+    /// Whether the straight line from the source, through the image's hops, reaches `point`.
+    ///
+    /// The path is traced back from `point`: each leg must go through the exit of its hop,
+    /// and meet no other portal before the entry of the next.
+    fn reaches(&self, point: Point, portals: &[(PortalId, Portal)]) -> bool {
+        let mut end = point;
+        let mut end_portal = None;
+        for (hop, &image) in self.hops.iter().zip(&self.positions[1..]).rev() {
+            let (exit_id, exit) = hop.exit;
+            let Some((t, x)) = exit.crossing(image, end - image) else {
+                return false;
+            };
+            let start = image + (end - image) * t;
+            if blocked(start, end, [Some(exit_id), end_portal], portals) {
+                return false;
+            }
+            let (entry_id, entry) = hop.entry;
+            end = entry.reverted_relative_position(Point::by_x(x));
+            end_portal = Some(entry_id);
+        }
+        !blocked(self.positions[0], end, [None, end_portal], portals)
+    }
+}
+
+/// Whether the segment from `start` to `end` crosses a portal, other than those it ends on.
+fn blocked(
+    start: Point,
+    end: Point,
+    ends: [Option<PortalId>; 2],
+    portals: &[(PortalId, Portal)],
+) -> bool {
+    portals
+        .iter()
+        .filter(|(id, _)| !ends.contains(&Some(*id)))
+        .any(|(_, portal)| portal.crossing(start, end - start).is_some())
+}
+
+/// How far a field is from a reference one.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Comparison {
+    /// Factor between the fields, fitted by least squares: `field ≃ scale × reference`.
+    pub scale: f64,
+    /// Root mean square of `field - scale × reference`, relative to that of `field`.
+    pub relative_error: f64,
+    /// Mean angle between the fields, in radians, where both are non-zero.
+    pub mean_angle: f64,
+}
+
+/// Compares the field of a channel to the reference, element by element of the reference,
+/// weighted by area.
+///
+/// # Panics
+///
+/// If the universes have different dimensions or channel counts.
+pub fn compare(field: &Universe, reference: &Universe, channel: usize) -> Comparison {
+    assert_eq!(
+        (field.width, field.height, field.channels().len()),
+        (
+            reference.width,
+            reference.height,
+            reference.channels().len()
+        ),
+        "compared universes must have the same dimensions and channels"
+    );
+    // (area, field, reference) of every reference element.
+    let mut samples = Vec::new();
+    reference.for_each_element(|origin, size, element| {
+        // Blocks on the edges may stick out of the universe.
+        let half = size as f64 / 2.0;
+        let center = Point {
+            x: (origin.0 as f64 + half).min(field.width as f64 - 0.5),
+            y: (origin.1 as f64 + half).min(field.height as f64 - 0.5),
+        };
+        let sampled = field
+            .get_from_point(center)
+            .unwrap()
+            .property(channel)
+            .field;
+        samples.push((
+            reference.area(origin, size),
+            sampled,
+            element.property(channel).field,
+        ));
+    });
+
+    let dot = |a: Point, b: Point| a.x * b.x + a.y * b.y;
+    let (mut cross, mut reference_2, mut field_2) = (0.0, 0.0, 0.0);
+    for &(area, field, reference) in &samples {
+        cross += area * dot(field, reference);
+        reference_2 += area * reference.magnitude_2();
+        field_2 += area * field.magnitude_2();
+    }
+    let scale = if reference_2 > 0.0 {
+        cross / reference_2
+    } else {
+        0.0
+    };
+
+    let (mut error_2, mut angles, mut angle_area) = (0.0, 0.0, 0.0);
+    for &(area, field, reference) in &samples {
+        error_2 += area * (field - reference * scale).magnitude_2();
+        let norms = field.magnitude() * reference.magnitude();
+        if norms > 0.0 {
+            angles += area * (dot(field, reference) / norms).clamp(-1.0, 1.0).acos();
+            angle_area += area;
+        }
+    }
+    Comparison {
+        scale,
+        relative_error: if field_2 > 0.0 {
+            (error_2 / field_2).sqrt()
+        } else {
+            0.0
+        },
+        mean_angle: if angle_area > 0.0 {
+            angles / angle_area
+        } else {
+            0.0
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ParticleParameters, PortalSet, Simulation, types::Channel};
+
+    fn p(x: f64, y: f64) -> Point {
+        Point { x, y }
+    }
+
+    /// A 40×40 universe of one mass channel, with a source of `value` at `cell`.
+    fn universe(cell: (u32, u32), value: f64) -> Universe {
+        let mut universe = Universe::new(40, 40, vec![Channel::mass()]);
+        universe[cell].element_mut().unwrap().properties[0].value = value;
+        universe
+    }
+
+    fn field(universe: &Universe, (x, y): (u32, u32)) -> Point {
+        universe[(x, y)].element().unwrap().property(0).field
+    }
+
+    /// Field of a source of `value` at `source`, seen from the center of `cell`.
+    fn expected(source: Point, value: f64, (x, y): (u32, u32)) -> Point {
+        let delta = p(x as f64 + 0.5, y as f64 + 0.5) - source;
+        delta * (Channel::mass().coupling * value / delta.magnitude_2())
+    }
+
+    fn assert_close(actual: Point, expected: Point) {
+        assert!(
+            (actual - expected).magnitude() < 1e-12,
+            "expected {expected}, got {actual}"
+        );
+    }
+
+    #[test]
+    fn a_lone_source_has_a_field_of_one_over_r() {
+        let reference = reference_field(&universe((20, 20), 2.0), 2);
+        for cell in [(21, 20), (5, 33), (39, 0), (20, 20)] {
+            let field = field(&reference, cell);
+            assert_close(field, expected(p(20.0, 20.0), 2.0, cell));
+            // Towards the source, a mass.
+            let towards = p(20.0, 20.0) - p(cell.0 as f64 + 0.5, cell.1 as f64 + 0.5);
+            assert!(field.x * towards.x + field.y * towards.y > 0.0);
+        }
+    }
+
+    #[test]
+    fn portals_show_the_image_of_a_source_where_their_exit_reaches() {
+        let mut universe = universe((20, 5), 1.0);
+        universe.add_portal_set(PortalSet::new(
+            Portal::new(p(10.0, 10.0), p(30.0, 10.0)),
+            Portal::new(p(10.0, 30.0), p(30.0, 30.0)),
+        ));
+        let reference = reference_field(&universe, 1);
+        let (source, image) = (p(20.0, 5.0), p(20.0, 25.0));
+
+        // In plain sight of the source, above the entry.
+        assert_close(field(&reference, (38, 8)), expected(source, 1.0, (38, 8)));
+        // Below the exit, hidden from the source by the entry, seeing its image instead.
+        assert_close(field(&reference, (20, 35)), expected(image, 1.0, (20, 35)));
+        // Below the exit, but out of its reach.
+        assert_close(field(&reference, (38, 31)), Point::default());
+    }
+
+    #[test]
+    fn gravitons_approach_the_reference() {
+        let universe = universe((20, 20), 1.0);
+        let reference = reference_field(&universe, 0);
+        let mut simulation = Simulation::new(
+            universe,
+            ParticleParameters::new(1.0, 128, 30),
+            ParticleParameters::new(1.0, 64, 5),
+        );
+        while simulation.step() {}
+        // Mostly the near field, which sub-gravitons blur over their range, and rays.
+        let comparison = compare(&simulation.universe, &reference, 0);
+        assert!(comparison.scale > 0.0, "{comparison:?}");
+        assert!(comparison.relative_error < 0.7, "{comparison:?}");
+        assert!(comparison.mean_angle < 0.25, "{comparison:?}");
+    }
+}
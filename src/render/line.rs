@@ -0,0 +1,448 @@
+//! Drawing segments on images.
+//!
+//! As in a [`Universe`](crate::Universe), the pixel `(x, y)` covers the square from `(x, y)`
+//! to `(x + 1, y + 1)`. Segments are clipped to the image first, so they may lie partly or
+//! entirely outside of it.
+
+use crate::types::Point;
+
+use image::{ImageBuffer, Pixel};
+
+use core::ops::{Deref, DerefMut};
+
+/// Width and smoothing of drawn lines.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Stroke {
+    /// In pixels.
+    pub width: f64,
+    pub antialiased: bool,
+}
+
+impl Default for Stroke {
+    fn default() -> Self {
+        Stroke {
+            width: 1.0,
+            antialiased: true,
+        }
+    }
+}
+
+/// Draws a segment as `stroke` describes, with the fitting one of the functions below.
+pub fn draw_stroke<P: Pixel<Subpixel = u8>, Container: Deref<Target = [u8]> + DerefMut>(
+    img: &mut ImageBuffer<P, Container>,
+    start: Point,
+    end: Point,
+    stroke: &Stroke,
+    color: P,
+) {
+    match (stroke.width > 1.0, stroke.antialiased) {
+        (false, false) => draw_line(img, start, end, color),
+        (false, true) => draw_line_aa(img, start, end, color),
+        (true, false) => fill_segment(img, start, end, stroke.width, color),
+        (true, true) => fill_segment_aa(img, start, end, stroke.width, color),
+    }
+}
+
+/// Draws a one pixel wide segment, without gaps nor smoothing.
+///
+/// https://en.wikipedia.org/wiki/Bresenham%27s_line_algorithm#All_cases
+pub fn draw_line<P: Pixel, Container: Deref<Target = [P::Subpixel]> + DerefMut>(
+    img: &mut ImageBuffer<P, Container>,
+    start: Point,
+    end: Point,
+    color: P,
+) {
+    let (width, height) = (img.width() as f64, img.height() as f64);
+    // With pixel centres on integers, the image spans from -0.5 to its size minus 0.5.
+    let Some((start, end)) = clip(
+        start - HALF,
+        end - HALF,
+        Point { x: -0.5, y: -0.5 },
+        Point {
+            x: width - 0.5,
+            y: height - 0.5,
+        },
+    ) else {
+        return;
+    };
+    let (mut x, mut y) = (start.x.round() as i64, start.y.round() as i64);
+    let (x1, y1) = (end.x.round() as i64, end.y.round() as i64);
+    let (dx, sx) = ((x1 - x).abs(), if x < x1 { 1 } else { -1 });
+    let (dy, sy) = (-(y1 - y).abs(), if y < y1 { 1 } else { -1 });
+    let mut error = dx + dy;
+    loop {
+        if let Some(pixel) = pixel_mut(img, x, y) {
+            *pixel = color;
+        }
+        if (x, y) == (x1, y1) {
+            break;
+        }
+        let e2 = 2 * error;
+        if e2 >= dy {
+            error += dy;
+            x += sx;
+        }
+        if e2 <= dx {
+            error += dx;
+            y += sy;
+        }
+    }
+}
+
+/// Draws a one pixel wide segment, blending each pixel with `color` as much as it's
+/// covered.
+///
+/// https://en.wikipedia.org/wiki/Xiaolin_Wu%27s_line_algorithm
+pub fn draw_line_aa<P: Pixel<Subpixel = u8>, Container: Deref<Target = [u8]> + DerefMut>(
+    img: &mut ImageBuffer<P, Container>,
+    start: Point,
+    end: Point,
+    color: P,
+) {
+    let (width, height) = (img.width() as f64, img.height() as f64);
+    // A pixel's margin, lines just outside the image still touching its border.
+    let Some((a, b)) = clip(
+        start - HALF,
+        end - HALF,
+        Point { x: -1.0, y: -1.0 },
+        Point {
+            x: width,
+            y: height,
+        },
+    ) else {
+        return;
+    };
+    let steep = (b.y - a.y).abs() > (b.x - a.x).abs();
+    let transpose = |p: Point| if steep { Point { x: p.y, y: p.x } } else { p };
+    let (mut a, mut b) = (transpose(a), transpose(b));
+    if a.x > b.x {
+        (a, b) = (b, a);
+    }
+    let (dx, dy) = (b.x - a.x, b.y - a.y);
+    let gradient = if dx == 0.0 { 1.0 } else { dy / dx };
+    let mut plot = |x: i64, y: i64, coverage: f64| {
+        let (x, y) = if steep { (y, x) } else { (x, y) };
+        if let Some(pixel) = pixel_mut(img, x, y) {
+            blend(pixel, color, coverage);
+        }
+    };
+    let fract = |v: f64| v - v.floor();
+
+    // Ends, covered as much as the segment reaches into their pixel.
+    let x_start = a.x.round();
+    let y_start = a.y + gradient * (x_start - a.x);
+    let gap = 1.0 - fract(a.x + 0.5);
+    let (x0, y0) = (x_start as i64, y_start.floor() as i64);
+    plot(x0, y0, (1.0 - fract(y_start)) * gap);
+    plot(x0, y0 + 1, fract(y_start) * gap);
+
+    let x_end = b.x.round();
+    let y_end = b.y + gradient * (x_end - b.x);
+    let gap = fract(b.x + 0.5);
+    let (x1, y1) = (x_end as i64, y_end.floor() as i64);
+    plot(x1, y1, (1.0 - fract(y_end)) * gap);
+    plot(x1, y1 + 1, fract(y_end) * gap);
+
+    let mut y = y_start + gradient;
+    for x in x0 + 1..x1 {
+        plot(x, y.floor() as i64, 1.0 - fract(y));
+        plot(x, y.floor() as i64 + 1, fract(y));
+        y += gradient;
+    }
+}
+
+/// Fills the pixels whose centre lies within `width / 2` of the segment from `start` to `end`,
+/// a disc if they're equal.
+pub fn fill_segment<P: Pixel, Container: Deref<Target = [P::Subpixel]> + DerefMut>(
+    img: &mut ImageBuffer<P, Container>,
+    start: Point,
+    end: Point,
+    width: f64,
+    color: P,
+) {
+    let radius = width / 2.0;
+    for_each_near(img, start, end, radius, |pixel, distance| {
+        if distance <= radius {
+            *pixel = color;
+        }
+    });
+}
+
+/// Like [`fill_segment`], blending the pixels on the border as much as they're covered.
+pub fn fill_segment_aa<P: Pixel<Subpixel = u8>, Container: Deref<Target = [u8]> + DerefMut>(
+    img: &mut ImageBuffer<P, Container>,
+    start: Point,
+    end: Point,
+    width: f64,
+    color: P,
+) {
+    let radius = width / 2.0;
+    for_each_near(img, start, end, radius + 0.5, |pixel, distance| {
+        blend(pixel, color, (radius + 0.5 - distance).clamp(0.0, 1.0));
+    });
+}
+
+/// Draws a line from `start` to `end`, with a head of two strokes at `end`.
+pub fn draw_arrow<P: Pixel, Container: Deref<Target = [P::Subpixel]> + DerefMut>(
+    img: &mut ImageBuffer<P, Container>,
+    start: Point,
+    end: Point,
+    color: P,
+) {
+    for (start, end) in arrow(start, end) {
+        draw_line(img, start, end, color);
+    }
+}
+
+/// Segments of an arrow from `start` to `end`: its shaft, then both strokes of its head.
+pub fn arrow(start: Point, end: Point) -> [(Point, Point); 3] {
+    /// Angle between the shaft and each stroke of the head.
+    const HEAD_ANGLE: f64 = std::f64::consts::PI * 5.0 / 6.0;
+    let shaft = end - start;
+    // A third of the shaft, but at least 2 pixels for the head to show.
+    let head = shaft * (shaft.magnitude() / 3.0).max(2.0) / shaft.magnitude();
+    [
+        (start, end),
+        (end, end + head * Point::from_angle(HEAD_ANGLE)),
+        (end, end + head * Point::from_angle(-HEAD_ANGLE)),
+    ]
+}
+
+/// From pixel corners to pixel centres.
+const HALF: (f64, f64) = (0.5, 0.5);
+
+/// The part of the segment inside the rectangle from `min` to `max`, if any.
+///
+/// https://en.wikipedia.org/wiki/Liang%E2%80%93Barsky_algorithm
+fn clip(start: Point, end: Point, min: Point, max: Point) -> Option<(Point, Point)> {
+    if ![start.x, start.y, end.x, end.y]
+        .iter()
+        .all(|v| v.is_finite())
+    {
+        return None;
+    }
+    let delta = end - start;
+    let (mut t0, mut t1) = (0f64, 1f64);
+    for (p, q) in [
+        (-delta.x, start.x - min.x),
+        (delta.x, max.x - start.x),
+        (-delta.y, start.y - min.y),
+        (delta.y, max.y - start.y),
+    ] {
+        if p == 0.0 {
+            // Parallel to this edge: entirely in or out.
+            if q < 0.0 {
+                return None;
+            }
+        } else if p < 0.0 {
+            t0 = t0.max(q / p);
+        } else {
+            t1 = t1.min(q / p);
+        }
+    }
+    (t0 <= t1).then(|| (start + delta * t0, start + delta * t1))
+}
+
+fn pixel_mut<P: Pixel, Container: Deref<Target = [P::Subpixel]> + DerefMut>(
+    img: &mut ImageBuffer<P, Container>,
+    x: i64,
+    y: i64,
+) -> Option<&mut P> {
+    let (x, y) = (u32::try_from(x).ok()?, u32::try_from(y).ok()?);
+    img.get_pixel_mut_checked(x, y)
+}
+
+/// Calls `f` with every pixel whose centre lies within `reach` of the segment, and that
+/// distance.
+fn for_each_near<P: Pixel, Container: Deref<Target = [P::Subpixel]> + DerefMut>(
+    img: &mut ImageBuffer<P, Container>,
+    start: Point,
+    end: Point,
+    reach: f64,
+    mut f: impl FnMut(&mut P, f64),
+) {
+    if ![start.x, start.y, end.x, end.y, reach]
+        .iter()
+        .all(|v| v.is_finite())
+    {
+        return;
+    }
+    let segment = end - start;
+    let length_2 = segment.magnitude_2();
+    let bound = |a: f64, b: f64, size: u32| {
+        let low = (a.min(b) - reach).floor().clamp(0.0, size as f64) as u32;
+        let high = (a.max(b) + reach).ceil().clamp(0.0, size as f64) as u32;
+        low..high
+    };
+    for y in bound(start.y, end.y, img.height()) {
+        for x in bound(start.x, end.x, img.width()) {
+            let offset = Point {
+                x: x as f64 + 0.5,
+                y: y as f64 + 0.5,
+            } - start;
+            // Fraction of the segment of the closest point to the centre.
+            let t = if length_2 > 0.0 {
+                ((offset.x * segment.x + offset.y * segment.y) / length_2).clamp(0.0, 1.0)
+            } else {
+                0.0
+            };
+            let distance = (offset - segment * t).magnitude();
+            if distance <= reach {
+                f(img.get_pixel_mut(x, y), distance);
+            }
+        }
+    }
+}
+
+/// Moves `pixel` towards `color` by `amount`, from 0 to 1.
+fn blend<P: Pixel<Subpixel = u8>>(pixel: &mut P, color: P, amount: f64) {
+    let amount = amount.clamp(0.0, 1.0);
+    *pixel = pixel.map2(&color, |a, b| {
+        (a as f64 + (b as f64 - a as f64) * amount).round() as u8
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use image::{GrayImage, Luma};
+
+    const ON: Luma<u8> = Luma([255]);
+
+    fn centre(x: u32, y: u32) -> Point {
+        Point {
+            x: x as f64 + 0.5,
+            y: y as f64 + 0.5,
+        }
+    }
+
+    fn lit(img: &GrayImage) -> Vec<(u32, u32)> {
+        img.enumerate_pixels()
+            .filter(|(_, _, p)| p.0[0] > 0)
+            .map(|(x, y, _)| (x, y))
+            .collect()
+    }
+
+    #[test]
+    fn lines_reach_both_ends_in_every_octant() {
+        for (dx, dy) in [
+            (7, 2),
+            (2, 7),
+            (-2, 7),
+            (-7, 2),
+            (-7, -2),
+            (-2, -7),
+            (2, -7),
+            (7, -2),
+        ] {
+            let mut img = GrayImage::new(21, 21);
+            let end = ((10 + dx) as u32, (10 + dy) as u32);
+            draw_line(&mut img, centre(10, 10), centre(end.0, end.1), ON);
+            let pixels = lit(&img);
+            assert_eq!(pixels.len(), 8, "octant ({dx}, {dy})");
+            assert!(pixels.contains(&(10, 10)) && pixels.contains(&end));
+            // Without gaps: each pixel touches another.
+            for &(x, y) in &pixels {
+                assert!(pixels.iter().any(|&(ox, oy)| {
+                    (ox, oy) != (x, y) && ox.abs_diff(x) <= 1 && oy.abs_diff(y) <= 1
+                }));
+            }
+        }
+    }
+
+    #[test]
+    fn lines_are_clipped_to_the_image() {
+        let mut img = GrayImage::new(10, 10);
+        draw_line(
+            &mut img,
+            Point { x: -50.0, y: 5.5 },
+            Point { x: 200.0, y: 5.5 },
+            ON,
+        );
+        assert_eq!(lit(&img), (0..10).map(|x| (x, 5)).collect::<Vec<_>>());
+
+        let mut img = GrayImage::new(10, 10);
+        draw_line(
+            &mut img,
+            Point { x: 3.5, y: -20.0 },
+            Point { x: 3.5, y: -1.0 },
+            ON,
+        );
+        draw_line(
+            &mut img,
+            Point { x: -5.0, y: 12.0 },
+            Point { x: 12.0, y: 30.0 },
+            ON,
+        );
+        draw_line(
+            &mut img,
+            Point {
+                x: f64::NAN,
+                y: 1.0,
+            },
+            Point { x: 2.0, y: 2.0 },
+            ON,
+        );
+        assert!(lit(&img).is_empty());
+    }
+
+    #[test]
+    fn lines_leaving_from_the_corner_do_not_underflow() {
+        let mut img = GrayImage::new(10, 10);
+        draw_line(&mut img, centre(0, 0), Point { x: -30.0, y: -10.0 }, ON);
+        draw_line(&mut img, centre(0, 9), Point { x: 5.0, y: -40.0 }, ON);
+        assert!(lit(&img).contains(&(0, 0)));
+    }
+
+    #[test]
+    fn antialiased_lines_share_their_coverage() {
+        // Through the pixels' centres, fully covered.
+        let mut img = GrayImage::new(10, 10);
+        draw_line_aa(&mut img, centre(1, 4), centre(8, 4), ON);
+        assert!((2..8).all(|x| img[(x, 4)] == ON));
+        assert!((0..10).all(|x| img[(x, 3)].0[0] == 0 && img[(x, 5)].0[0] == 0));
+
+        // Between two rows, half on each.
+        let mut img = GrayImage::new(10, 10);
+        draw_line_aa(
+            &mut img,
+            Point { x: 1.5, y: 5.0 },
+            Point { x: 8.5, y: 5.0 },
+            ON,
+        );
+        assert!((2..8).all(|x| img[(x, 4)].0[0].abs_diff(128) <= 1));
+        assert!((2..8).all(|x| img[(x, 5)].0[0].abs_diff(128) <= 1));
+
+        // Steep and leaving the image.
+        let mut img = GrayImage::new(10, 10);
+        draw_line_aa(
+            &mut img,
+            Point { x: 4.5, y: -30.0 },
+            Point { x: 6.5, y: 40.0 },
+            ON,
+        );
+        assert!((0..10).all(|y| (0..10).any(|x| img[(x, y)].0[0] > 0)));
+    }
+
+    #[test]
+    fn wide_segments_cover_their_width() {
+        let mut img = GrayImage::new(20, 20);
+        fill_segment(&mut img, centre(2, 10), centre(17, 10), 3.0, ON);
+        for x in 2..=17 {
+            assert!((9..=11).all(|y| img[(x, y)] == ON));
+            assert!(img[(x, 8)].0[0] == 0 && img[(x, 12)].0[0] == 0);
+        }
+
+        // Diagonal, as wide across.
+        let mut img = GrayImage::new(20, 20);
+        fill_segment(&mut img, centre(2, 2), centre(17, 17), 3.0, ON);
+        assert!(img[(10, 9)] == ON && img[(9, 10)] == ON);
+        assert!(img[(12, 9)].0[0] == 0 && img[(9, 12)].0[0] == 0);
+
+        let mut img = GrayImage::new(20, 20);
+        fill_segment_aa(&mut img, centre(2, 10), centre(17, 10), 2.0, ON);
+        assert!(img[(10, 10)] == ON);
+        assert!(img[(10, 9)].0[0].abs_diff(128) <= 1 && img[(10, 11)].0[0].abs_diff(128) <= 1);
+    }
+}
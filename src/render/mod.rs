@@ -0,0 +1,252 @@
+//! Turning a universe's fields into images.
+
+mod line;
+mod normalization;
+mod portals;
+mod quiver;
+mod sources;
+mod streamlines;
+pub use line::{
+    Stroke, arrow, draw_arrow, draw_line, draw_line_aa, draw_stroke, fill_segment, fill_segment_aa,
+};
+pub use normalization::{Curve, FrameScale, FrameScales, Normalization, Range, Scale};
+pub use portals::{PORTAL_COLOURS, PortalStyle};
+pub use quiver::Quiver;
+pub use sources::{SourceBlend, Sources};
+pub use streamlines::Streamlines;
+
+use crate::types::{Point, Universe};
+
+use colorgrad::{Color, Gradient};
+use image::{DynamicImage, ImageBuffer, Rgb, RgbImage};
+use rayon::prelude::*;
+
+use std::{
+    f64::consts::TAU,
+    fs::File,
+    io::{self, BufWriter},
+    path::Path,
+    str::FromStr,
+};
+
+/// What the colour of a pixel shows of the field.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Colouring {
+    /// The magnitude, through a gradient.
+    #[default]
+    Magnitude,
+    /// The angle as hue and the magnitude as brightness, ignoring the gradient.
+    Direction,
+}
+
+/// How a universe's field is turned into an image.
+pub struct Style {
+    /// Colours of the magnitude, from 0 to 1.
+    pub gradient: Box<dyn Gradient>,
+    pub colouring: Colouring,
+    pub normalization: Normalization,
+    pub portals: PortalStyle,
+    pub sources: Option<Sources>,
+    pub streamlines: Option<Streamlines>,
+    pub quiver: Option<Quiver>,
+}
+
+impl Default for Style {
+    fn default() -> Self {
+        Style {
+            gradient: colorgrad::preset::viridis().boxed(),
+            colouring: Colouring::default(),
+            normalization: Normalization::default(),
+            portals: PortalStyle::default(),
+            sources: None,
+            streamlines: None,
+            quiver: None,
+        }
+    }
+}
+
+impl FromStr for Colouring {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "magnitude" => Ok(Colouring::Magnitude),
+            "direction" => Ok(Colouring::Direction),
+            _ => Err(format!(
+                "unknown colouring `{s}`, expected `magnitude` or `direction`"
+            )),
+        }
+    }
+}
+
+impl Universe {
+    /// Brings the sources to `[0, 1]`, relative to the largest in magnitude so that empty
+    /// cells stay at 0, and the field's magnitudes through `scale`.
+    fn normalize(&self, channel: usize, scale: &Scale) -> Universe {
+        let mut mass_max = 0f64;
+        self.for_each_element(|_, _, element| {
+            mass_max = mass_max.max(element.property(channel).value.abs());
+        });
+        let mut new = self.clone();
+        new.for_each_element_mut(|_, _, element| {
+            let property = element.property_mut(channel);
+            let mass = property.value.abs();
+            property.value = if mass_max > 0.0 { mass / mass_max } else { 0.0 };
+            let mag = property.field.magnitude();
+            property.field *= if mag > 0.0 {
+                scale.apply(mag) / mag
+            } else {
+                0.0
+            };
+        });
+        new
+    }
+    pub fn to_image(&self) -> DynamicImage {
+        self.to_image_with(&colorgrad::preset::viridis())
+    }
+    /// Renders the field of the first channel.
+    pub fn to_image_with(&self, gradient: &dyn Gradient) -> DynamicImage {
+        self.to_layer_image(0, gradient)
+    }
+    /// Renders the field of one channel.
+    pub fn to_layer_image(&self, channel: usize, gradient: &dyn Gradient) -> DynamicImage {
+        let scale = Normalization::default().fit(self, channel);
+        let portals = PortalStyle::default();
+        DynamicImage::ImageRgb8(self.render(channel, &scale, &portals, None, sampled(gradient)))
+    }
+    /// Renders the field of one channel, its angle as hue and its magnitude as brightness.
+    pub fn to_direction_image(&self, channel: usize) -> DynamicImage {
+        let scale = Normalization::default().fit(self, channel);
+        let portals = PortalStyle::default();
+        DynamicImage::ImageRgb8(self.render(channel, &scale, &portals, None, direction_colour))
+    }
+    /// Renders the field of one channel as described by `style`, overlays included.
+    pub fn to_styled_image(&self, channel: usize, style: &Style) -> DynamicImage {
+        let scale = style.normalization.fit(self, channel);
+        self.to_scaled_image(channel, style, &scale)
+    }
+    /// Like [`Universe::to_styled_image`], with a scale fitted beforehand, to another frame
+    /// for instance, instead of `style`'s normalization.
+    pub fn to_scaled_image(&self, channel: usize, style: &Style, scale: &Scale) -> DynamicImage {
+        let (portals, sources) = (&style.portals, style.sources.as_ref());
+        let mut img = match style.colouring {
+            Colouring::Magnitude => {
+                self.render(channel, scale, portals, sources, sampled(&*style.gradient))
+            }
+            Colouring::Direction => self.render(channel, scale, portals, sources, direction_colour),
+        };
+        if let Some(streamlines) = &style.streamlines {
+            self.draw_streamlines(&mut img, channel, streamlines);
+        }
+        if let Some(quiver) = &style.quiver {
+            self.draw_quiver(&mut img, channel, quiver);
+        }
+        DynamicImage::ImageRgb8(img)
+    }
+    /// Renders the portals, the field of `channel` coloured by `colour` once normalized, and
+    /// its sources if any.
+    fn render(
+        &self,
+        channel: usize,
+        scale: &Scale,
+        portals: &PortalStyle,
+        sources: Option<&Sources>,
+        colour: impl Fn(Point) -> [u8; 3] + Sync,
+    ) -> RgbImage {
+        let universe = self.normalize(channel, scale);
+        //* Create image
+        let mut img = ImageBuffer::new(universe.width, universe.height);
+        //* Draw portals
+        // On a mask first, the field is blended with them afterwards.
+        let mask = self.portal_mask(portals);
+        //* Draw field(s)
+        img.par_enumerate_pixels_mut().for_each(|(x, y, pixel)| {
+            let property = universe[(x, y)].element().unwrap().property(channel);
+            let mut rgb = match sources {
+                Some(sources) if !sources.blend.shows_field() => [0, 0, 0],
+                _ => colour(property.field),
+            };
+            if let Some(sources) = sources {
+                let amount = property.value;
+                let empty = |(x, y): (u32, u32)| {
+                    let element = universe[(x, y)].element().unwrap();
+                    element.property(channel).value == 0.0
+                };
+                // Inner pixels of a source next to an empty one.
+                let edge = amount > 0.0
+                    && [
+                        (x.wrapping_sub(1), y),
+                        (x + 1, y),
+                        (x, y.wrapping_sub(1)),
+                        (x, y + 1),
+                    ]
+                    .into_iter()
+                    .filter(|&(x, y)| x < universe.width && y < universe.height)
+                    .any(empty);
+                rgb = sources.apply(rgb, amount, edge);
+            }
+            let [r, g, b] = rgb;
+
+            let [pair] = mask[(x, y)].0;
+            if pair > 0 {
+                let [pr, pg, pb] = portals.colour(pair as usize - 1).0;
+                *pixel = Rgb([
+                    ((r as f64 + pr as f64) * 0.5) as u8,
+                    ((g as f64 + pg as f64) * 0.5) as u8,
+                    ((b as f64 + pb as f64) * 0.5) as u8,
+                ]);
+            } else {
+                *pixel = Rgb([r, g, b]);
+            }
+        });
+        img
+    }
+}
+
+/// Saves `image` as a PNG, with the magnitudes at either end of its colours, and their
+/// curve, as text chunks.
+pub fn save_with_scale(
+    image: &DynamicImage,
+    scale: &Scale,
+    path: impl AsRef<Path>,
+) -> io::Result<()> {
+    let image = image.to_rgb8();
+    let file = BufWriter::new(File::create(path)?);
+    let mut encoder = png::Encoder::new(file, image.width(), image.height());
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+    let chunks = [
+        ("Field low", scale.low.to_string()),
+        ("Field high", scale.high.to_string()),
+        ("Field curve", scale.curve.to_string()),
+    ];
+    for (keyword, text) in chunks {
+        encoder
+            .add_text_chunk(keyword.to_string(), text)
+            .map_err(io::Error::other)?;
+    }
+    let mut writer = encoder.write_header().map_err(io::Error::other)?;
+    writer.write_image_data(&image).map_err(io::Error::other)?;
+    writer.finish().map_err(io::Error::other)
+}
+
+/// Colours a normalized field's magnitude through `gradient`.
+fn sampled(gradient: &dyn Gradient) -> impl Fn(Point) -> [u8; 3] + Sync + use<> {
+    // Gradients aren't `Sync`, so they're sampled ahead of the parallel pass.
+    const SAMPLES: usize = 1024;
+    let colours: Box<[[u8; 4]]> = (0..SAMPLES)
+        .map(|i| gradient.at(i as f32 / (SAMPLES - 1) as f32).to_rgba8())
+        .collect();
+    move |field| {
+        let sample = (field.magnitude() * (SAMPLES - 1) as f64).round() as usize;
+        let [r, g, b, ..] = colours[sample.min(SAMPLES - 1)];
+        [r, g, b]
+    }
+}
+
+/// Colours a normalized field's angle as hue and its magnitude as brightness.
+fn direction_colour(field: Point) -> [u8; 3] {
+    let hue = field.y.atan2(field.x).rem_euclid(TAU).to_degrees();
+    let value = field.magnitude().min(1.0);
+    let [r, g, b, ..] = Color::from_hsva(hue as f32, 1.0, value as f32, 1.0).to_rgba8();
+    [r, g, b]
+}
@@ -0,0 +1,362 @@
+//! Mapping field magnitudes to `[0, 1]`, the position along a colour gradient.
+//!
+//! A [`Normalization`] is fitted to a field, giving a [`Scale`]. In an animation,
+//! [`FrameScales`] picks the frames each scale is fitted to, as [`FrameScale`] says.
+
+use crate::{simulation::Simulation, types::Universe};
+
+use std::{
+    fmt::{Display, Formatter, Result as FmtResult},
+    str::FromStr,
+};
+
+/// How field magnitudes are brought to `[0, 1]` before colouring.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Normalization {
+    pub range: Range,
+    pub curve: Curve,
+}
+
+/// Which magnitudes go to either end of the gradient, those outside being clipped.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum Range {
+    /// From 0 to the largest magnitude.
+    #[default]
+    Max,
+    /// Between two percentiles of the magnitudes, weighted by area, like 1 and 99.
+    Percentile { low: f64, high: f64 },
+    /// Between fixed magnitudes.
+    Fixed { min: f64, max: f64 },
+}
+
+/// How magnitudes are spread between the ends of the range.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum Curve {
+    #[default]
+    Linear,
+    /// Logarithmically, over at most `decades` powers of ten below the top of the range:
+    /// the near field no longer hides fields orders of magnitude weaker.
+    Log { decades: f64 },
+    /// Linearly, then raised to `gamma`; below 1 brightens the weak fields.
+    Power { gamma: f64 },
+}
+
+/// A normalization fitted to a field: maps its magnitudes to `[0, 1]`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Scale {
+    pub low: f64,
+    pub high: f64,
+    pub curve: Curve,
+}
+
+impl Scale {
+    pub fn apply(&self, magnitude: f64) -> f64 {
+        let Scale { low, high, curve } = *self;
+        let t = match curve {
+            Curve::Log { decades } => {
+                let low = low.max(high * 10f64.powf(-decades));
+                (magnitude / low).log10() / (high / low).log10()
+            }
+            Curve::Linear | Curve::Power { .. } => (magnitude - low) / (high - low),
+        };
+        // A field without range, NaN here, is all at the bottom.
+        let t = if t.is_nan() { 0.0 } else { t.clamp(0.0, 1.0) };
+        match curve {
+            Curve::Power { gamma } => t.powf(gamma),
+            Curve::Linear | Curve::Log { .. } => t,
+        }
+    }
+
+    /// The smallest scale covering both ranges, with `self`'s curve.
+    pub fn union(&self, other: &Scale) -> Scale {
+        Scale {
+            low: self.low.min(other.low),
+            high: self.high.max(other.high),
+            curve: self.curve,
+        }
+    }
+}
+
+/// Which frames of an animation the scale of each is fitted to.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum FrameScale {
+    /// Each frame its own: the colours of the whole field shift as it grows.
+    #[default]
+    Each,
+    /// Every frame so far, the range only ever widening.
+    Running,
+    /// The last frame.
+    Final,
+    /// The frame of that index.
+    Frame(u32),
+}
+
+impl FrameScale {
+    /// The frame fitted ahead of the animation, out of `steps`, if any.
+    pub fn ahead(&self, steps: u32) -> Option<u32> {
+        match *self {
+            FrameScale::Each | FrameScale::Running => None,
+            FrameScale::Final => Some(steps),
+            FrameScale::Frame(frame) => Some(frame),
+        }
+    }
+}
+
+/// The scales of every channel through an animation, picked as its [`FrameScale`] says.
+#[derive(Debug, Clone)]
+pub struct FrameScales {
+    normalization: Normalization,
+    frame_scale: FrameScale,
+    /// Fitted ahead to the chosen frame, or to the frames so far when running.
+    scales: Vec<Option<Scale>>,
+}
+
+impl FrameScales {
+    /// Scales for the frames of `simulation` from its current step on, simulating a copy
+    /// up to the frame fitted ahead, if any. Fails if that frame is not to be rendered.
+    pub fn new(
+        simulation: &Simulation,
+        normalization: Normalization,
+        frame_scale: FrameScale,
+    ) -> Result<FrameScales, String> {
+        let channels = simulation.universe.channels().len();
+        let (start, steps) = (simulation.step_index(), simulation.steps());
+        let scales = match frame_scale.ahead(steps) {
+            None => vec![None; channels],
+            Some(frame) if frame < start || frame > steps => {
+                return Err(format!(
+                    "Frame {frame} is outside of the frames to render, {start} to {steps}"
+                ));
+            }
+            Some(frame) => {
+                let mut ahead = simulation.clone();
+                while ahead.step_index() < frame && ahead.step() {}
+                (0..channels)
+                    .map(|channel| Some(normalization.fit(&ahead.universe, channel)))
+                    .collect()
+            }
+        };
+        Ok(FrameScales {
+            normalization,
+            frame_scale,
+            scales,
+        })
+    }
+
+    /// The scale of `channel` in the next frame, `universe`: frames must come in order.
+    pub fn fit(&mut self, universe: &Universe, channel: usize) -> Scale {
+        let normalization = self.normalization;
+        let fit = || normalization.fit(universe, channel);
+        match (self.frame_scale, self.scales[channel]) {
+            (FrameScale::Each, _) => fit(),
+            (FrameScale::Running, previous) => {
+                let scale = fit();
+                let scale = previous.map_or(scale, |previous| previous.union(&scale));
+                self.scales[channel] = Some(scale);
+                scale
+            }
+            (_, fixed) => fixed.unwrap_or_else(fit),
+        }
+    }
+}
+
+impl Normalization {
+    /// Fits the range to the field of `channel`.
+    pub fn fit(&self, universe: &Universe, channel: usize) -> Scale {
+        let (low, high) = match self.range {
+            Range::Max => {
+                let mut max = 0f64;
+                universe.for_each_element(|_, _, element| {
+                    max = max.max(element.property(channel).field.magnitude());
+                });
+                (0.0, max)
+            }
+            Range::Percentile { low, high } => {
+                let mut magnitudes = Vec::new();
+                universe.for_each_element(|origin, size, element| {
+                    let magnitude = element.property(channel).field.magnitude();
+                    magnitudes.push((magnitude, universe.area(origin, size)));
+                });
+                magnitudes.sort_unstable_by(|a, b| a.0.total_cmp(&b.0));
+                let area = universe.width as f64 * universe.height as f64;
+                let percentile = |p: f64| {
+                    let mut covered = 0.0;
+                    magnitudes
+                        .iter()
+                        .find(|(_, cell)| {
+                            covered += cell;
+                            covered >= p / 100.0 * area
+                        })
+                        .or(magnitudes.last())
+                        .map_or(0.0, |(magnitude, _)| *magnitude)
+                };
+                (percentile(low), percentile(high))
+            }
+            Range::Fixed { min, max } => (min, max),
+        };
+        Scale {
+            low,
+            high,
+            curve: self.curve,
+        }
+    }
+}
+
+/// Splits `name:a,b` into its name and numbers.
+fn parameters(s: &str) -> Result<(&str, Vec<f64>), String> {
+    let Some((name, numbers)) = s.split_once(':') else {
+        return Ok((s, Vec::new()));
+    };
+    let numbers = numbers
+        .split(',')
+        .map(|n| {
+            n.trim()
+                .parse()
+                .map_err(|_| format!("invalid number `{n}`"))
+        })
+        .collect::<Result<_, _>>()?;
+    Ok((name, numbers))
+}
+
+impl FromStr for Range {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match parameters(s)? {
+            ("max", numbers) if numbers.is_empty() => Ok(Range::Max),
+            ("percentile", numbers) => match numbers[..] {
+                [low, high] if 0.0 <= low && low < high && high <= 100.0 => {
+                    Ok(Range::Percentile { low, high })
+                }
+                _ => Err("expected `percentile:<low>,<high>`, within 0 and 100".into()),
+            },
+            ("fixed", numbers) => match numbers[..] {
+                [min, max] if min < max => Ok(Range::Fixed { min, max }),
+                _ => Err("expected `fixed:<min>,<max>`".into()),
+            },
+            _ => Err(format!(
+                "unknown range `{s}`, expected `max`, `percentile:<low>,<high>` or `fixed:<min>,<max>`"
+            )),
+        }
+    }
+}
+
+impl FromStr for FrameScale {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once(':') {
+            None if s == "each" => Ok(FrameScale::Each),
+            None if s == "running" => Ok(FrameScale::Running),
+            None if s == "final" => Ok(FrameScale::Final),
+            Some(("frame", index)) => index
+                .parse()
+                .map(FrameScale::Frame)
+                .map_err(|_| format!("invalid frame `{index}`")),
+            _ => Err(format!(
+                "unknown frame scale `{s}`, expected `each`, `running`, `final` or `frame:<n>`"
+            )),
+        }
+    }
+}
+
+/// Decades shown by `log` without a number.
+const DEFAULT_DECADES: f64 = 4.0;
+
+impl FromStr for Curve {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match parameters(s)? {
+            ("linear", numbers) if numbers.is_empty() => Ok(Curve::Linear),
+            ("log", numbers) => match numbers[..] {
+                [] => Ok(Curve::Log {
+                    decades: DEFAULT_DECADES,
+                }),
+                [decades] if decades > 0.0 => Ok(Curve::Log { decades }),
+                _ => Err("expected `log:<decades>`, positive".into()),
+            },
+            ("gamma", numbers) => match numbers[..] {
+                [gamma] if gamma > 0.0 => Ok(Curve::Power { gamma }),
+                _ => Err("expected `gamma:<exponent>`, positive".into()),
+            },
+            _ => Err(format!(
+                "unknown curve `{s}`, expected `linear`, `log[:<decades>]` or `gamma:<exponent>`"
+            )),
+        }
+    }
+}
+
+/// In the syntax parsed by [`Curve::from_str`].
+impl Display for Curve {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        match self {
+            Curve::Linear => write!(f, "linear"),
+            Curve::Log { decades } => write!(f, "log:{decades}"),
+            Curve::Power { gamma } => write!(f, "gamma:{gamma}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ParticleParameters, Point, types::Channel};
+
+    /// A simulation of a single source, whose field grows as gravitons spread.
+    fn simulation() -> Simulation {
+        let mut universe = Universe::new(20, 20, vec![Channel::mass()]);
+        universe[(10, 10)].element_mut().unwrap().properties[0].value = 1.0;
+        let graviton = ParticleParameters::new(1.0, 16, 8);
+        Simulation::new(universe, graviton, ParticleParameters::new(0.9, 8, 4))
+    }
+
+    #[test]
+    fn running_scales_only_widen() {
+        let mut simulation = simulation();
+        let mut scales =
+            FrameScales::new(&simulation, Normalization::default(), FrameScale::Running).unwrap();
+        let mut previous = scales.fit(&simulation.universe, 0);
+        while simulation.step() {
+            let scale = scales.fit(&simulation.universe, 0);
+            let own = Normalization::default().fit(&simulation.universe, 0);
+            assert_eq!(scale, previous.union(&own));
+            previous = scale;
+        }
+    }
+
+    #[test]
+    fn chosen_frames_are_fitted_ahead() {
+        let normalization = Normalization::default();
+        let mut simulation = simulation();
+        let mut frames = vec![normalization.fit(&simulation.universe, 0)];
+        let mut ahead = simulation.clone();
+        while ahead.step() {
+            frames.push(normalization.fit(&ahead.universe, 0));
+        }
+        let mut last = FrameScales::new(&simulation, normalization, FrameScale::Final).unwrap();
+        let mut third = FrameScales::new(&simulation, normalization, FrameScale::Frame(3)).unwrap();
+        loop {
+            assert_eq!(last.fit(&simulation.universe, 0), frames[frames.len() - 1]);
+            assert_eq!(third.fit(&simulation.universe, 0), frames[3]);
+            if !simulation.step() {
+                break;
+            }
+        }
+        let late = FrameScales::new(&simulation, normalization, FrameScale::Frame(3));
+        assert!(late.is_err());
+    }
+
+    #[test]
+    fn percentiles_weigh_only_the_area_inside_the_universe() {
+        // The 16×16 block in the corner covers 64% of the universe, the other three the rest.
+        let mut universe = Universe::adaptive(20, 20, 16, vec![Channel::mass()]);
+        universe.for_each_element_mut(|origin, _, element| {
+            let x = if origin == (0, 0) { 2.0 } else { 1.0 };
+            element.properties[0].field = Point { x, y: 0.0 };
+        });
+        let percentile = |low, high| Normalization {
+            range: Range::Percentile { low, high },
+            curve: Curve::Linear,
+        };
+        assert_eq!(percentile(30.0, 50.0).fit(&universe, 0).low, 1.0);
+        assert_eq!(percentile(30.0, 50.0).fit(&universe, 0).high, 2.0);
+    }
+}
@@ -0,0 +1,87 @@
+use super::{draw_arrow, fill_segment};
+use crate::types::{Point, Portal, Universe};
+
+use image::{ImageBuffer, Luma, Rgb};
+
+/// Default colours of the portal pairs, in turn.
+pub const PORTAL_COLOURS: [Rgb<u8>; 8] = [
+    Rgb([192, 32, 32]),
+    Rgb([32, 144, 224]),
+    Rgb([240, 176, 32]),
+    Rgb([48, 192, 96]),
+    Rgb([176, 80, 208]),
+    Rgb([240, 112, 176]),
+    Rgb([160, 96, 48]),
+    Rgb([160, 160, 160]),
+];
+
+/// How portals are drawn.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PortalStyle {
+    /// Colours of the pairs of portals, in turn.
+    pub colours: Vec<Rgb<u8>>,
+    /// Thickness of the portals, in pixels.
+    pub width: f64,
+    /// Whether to mark the `point_a` end of each portal with a dot, and the side motion
+    /// comes out of with an arrow: crossing a portal along its arrow, one leaves the other
+    /// along its own.
+    pub markers: bool,
+}
+
+impl Default for PortalStyle {
+    fn default() -> Self {
+        PortalStyle {
+            colours: PORTAL_COLOURS.to_vec(),
+            width: 3.0,
+            markers: true,
+        }
+    }
+}
+
+impl PortalStyle {
+    /// Colour of the `i`th pair of portals.
+    pub fn colour(&self, i: usize) -> Rgb<u8> {
+        match self.colours.len() {
+            0 => PORTAL_COLOURS[i % PORTAL_COLOURS.len()],
+            n => self.colours[i % n],
+        }
+    }
+}
+
+/// Index of the pair of portals drawn on a pixel plus one, 0 where there's none.
+pub(super) type PortalMask = ImageBuffer<Luma<u16>, Vec<u16>>;
+
+impl Universe {
+    /// Draws the portals on a mask, for the field to be blended with their colours.
+    pub(super) fn portal_mask(&self, style: &PortalStyle) -> PortalMask {
+        let mut mask = PortalMask::new(self.width, self.height);
+        for (i, portalset) in self.portals().iter().enumerate() {
+            let pair = Luma([(i + 1).min(u16::MAX as usize) as u16]);
+            for portal in [portalset.a, portalset.b] {
+                fill_segment(&mut mask, portal.point_a, portal.point_b, style.width, pair);
+                if style.markers {
+                    draw_markers(&mut mask, &portal, style.width, pair);
+                }
+            }
+        }
+        mask
+    }
+}
+
+/// Draws a dot on the `point_a` end of `portal`, and an arrow from its middle towards the
+/// side motion comes out of.
+fn draw_markers(mask: &mut PortalMask, portal: &Portal, width: f64, pair: Luma<u16>) {
+    fill_segment(
+        mask,
+        portal.point_a,
+        portal.point_a,
+        width * 2.0 + 2.0,
+        pair,
+    );
+    let along = portal.point_b - portal.point_a;
+    // The side of positive relative `y`, kept through teleports.
+    let normal = Point::by_y(1.0) * along.direction();
+    let length = (portal.size() / 4.0).clamp(4.0, 16.0) + width / 2.0;
+    let middle = portal.point_a + along * 0.5;
+    draw_arrow(mask, middle, middle + normal * length, pair);
+}
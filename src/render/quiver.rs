@@ -0,0 +1,80 @@
+use super::{Stroke, arrow, draw_stroke};
+use crate::types::{Point, Universe};
+
+use image::{Rgb, RgbImage};
+
+/// How arrows showing the field's direction are laid out and drawn.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Quiver {
+    /// Distance between arrows, on a grid over the whole universe.
+    pub spacing: u32,
+    /// Length of the strongest arrow, relative to `spacing`.
+    pub scale: f64,
+    pub stroke: Stroke,
+    pub colour: Rgb<u8>,
+}
+
+impl Quiver {
+    pub fn new(spacing: u32) -> Self {
+        Quiver {
+            spacing,
+            scale: 0.9,
+            stroke: Stroke::default(),
+            colour: Rgb([255, 255, 255]),
+        }
+    }
+}
+
+impl Universe {
+    /// Arrows of `channel`'s field, sampled at the centre of every cell of the grid.
+    ///
+    /// Arrows are centred on their sample and scaled by the field's magnitude, the strongest
+    /// one being `scale` times `spacing` long, so none overlaps its neighbours.
+    pub fn quiver(&self, channel: usize, options: &Quiver) -> Vec<(Point, Point)> {
+        let spacing = options.spacing.max(1);
+        let samples: Vec<(Point, Point)> = (spacing / 2..self.height)
+            .step_by(spacing as usize)
+            .flat_map(|y| {
+                (spacing / 2..self.width)
+                    .step_by(spacing as usize)
+                    .map(move |x| (x, y))
+            })
+            .filter_map(|(x, y)| {
+                let field = self[(x, y)].element()?.property(channel).field;
+                let centre = Point {
+                    x: x as f64 + 0.5,
+                    y: y as f64 + 0.5,
+                };
+                Some((centre, field))
+            })
+            .collect();
+        let max = samples
+            .iter()
+            .map(|(_, field)| field.magnitude())
+            .fold(0.0, f64::max);
+        if !max.is_normal() {
+            return Vec::new();
+        }
+        let length = options.scale * spacing as f64 / max;
+        samples
+            .into_iter()
+            .map(|(centre, field)| {
+                let half = field * (length / 2.0);
+                (centre - half, centre + half)
+            })
+            // Arrows under a pixel long would only be a dot.
+            .filter(|(start, end)| (*end - *start).magnitude() >= 1.0)
+            .collect()
+    }
+
+    /// Draws arrows of `channel`'s field over `img`.
+    pub fn draw_quiver(&self, img: &mut RgbImage, channel: usize, options: &Quiver) {
+        for (start, end) in self
+            .quiver(channel, options)
+            .into_iter()
+            .flat_map(|(a, b)| arrow(a, b))
+        {
+            draw_stroke(img, start, end, &options.stroke, options.colour);
+        }
+    }
+}
@@ -0,0 +1,73 @@
+use image::Rgb;
+
+use std::{array, str::FromStr};
+
+/// How the sources of a channel are drawn along with its field.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Sources {
+    pub blend: SourceBlend,
+    pub colour: Rgb<u8>,
+}
+
+impl Sources {
+    pub fn new(blend: SourceBlend) -> Self {
+        Sources {
+            blend,
+            colour: Rgb([255, 255, 255]),
+        }
+    }
+
+    /// Colour of a pixel of field `base`, holding `amount` of the largest source in
+    /// magnitude, and lying on the edge of a source if `edge`.
+    pub(super) fn apply(&self, base: [u8; 3], amount: f64, edge: bool) -> [u8; 3] {
+        let colour = self.colour.0;
+        let mix = |target: [f64; 3]| {
+            array::from_fn(|i| {
+                (base[i] as f64 + (target[i] - base[i] as f64) * amount).round() as u8
+            })
+        };
+        match self.blend {
+            SourceBlend::Alone | SourceBlend::Alpha => mix(colour.map(f64::from)),
+            SourceBlend::Tint => mix(array::from_fn(|i| {
+                base[i] as f64 * colour[i] as f64 / 255.0
+            })),
+            SourceBlend::Contour if edge => colour,
+            SourceBlend::Contour => base,
+        }
+    }
+}
+
+/// How the sources are blended with the field.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SourceBlend {
+    /// Over black, without the field.
+    Alone,
+    /// Their outline only, over the field.
+    Contour,
+    /// Multiplying the field by their colour, as much as they're strong.
+    Tint,
+    /// Their colour over the field, as opaque as they're strong.
+    Alpha,
+}
+
+impl SourceBlend {
+    /// Whether the field is drawn under the sources.
+    pub(super) fn shows_field(self) -> bool {
+        self != SourceBlend::Alone
+    }
+}
+
+impl FromStr for SourceBlend {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "alone" => Ok(SourceBlend::Alone),
+            "contour" => Ok(SourceBlend::Contour),
+            "tint" => Ok(SourceBlend::Tint),
+            "alpha" => Ok(SourceBlend::Alpha),
+            _ => Err(format!(
+                "unknown blend `{s}`, expected `alone`, `contour`, `tint` or `alpha`"
+            )),
+        }
+    }
+}
@@ -0,0 +1,108 @@
+use super::{Stroke, draw_stroke};
+use crate::types::{Point, Universe};
+
+use image::{Rgb, RgbImage};
+
+/// How field lines are seeded, traced and drawn.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Streamlines {
+    /// Distance between seed points, on a grid over the whole universe.
+    pub spacing: u32,
+    /// Length of each integration step, in pixels.
+    pub step: f64,
+    /// Bound on the length of a line, each way from its seed.
+    pub max_length: f64,
+    pub stroke: Stroke,
+    pub colour: Rgb<u8>,
+}
+
+impl Streamlines {
+    pub fn new(spacing: u32) -> Self {
+        Streamlines {
+            spacing,
+            step: 0.5,
+            max_length: 1000.0,
+            stroke: Stroke::default(),
+            colour: Rgb([255, 255, 255]),
+        }
+    }
+}
+
+impl Universe {
+    /// Field lines of `channel`, traced both ways from every seed point.
+    ///
+    /// Lines go through portals as particles do, starting a new polyline on the other side,
+    /// and stop where the field vanishes or turns back, at sources and sinks.
+    pub fn streamlines(&self, channel: usize, options: &Streamlines) -> Vec<Vec<Point>> {
+        let spacing = options.spacing.max(1);
+        let mut lines = Vec::new();
+        for y in (spacing / 2..self.height).step_by(spacing as usize) {
+            for x in (spacing / 2..self.width).step_by(spacing as usize) {
+                let seed = Point {
+                    x: x as f64 + 0.5,
+                    y: y as f64 + 0.5,
+                };
+                for sense in [1.0, -1.0] {
+                    self.trace(&mut lines, seed, sense, channel, options);
+                }
+            }
+        }
+        lines
+    }
+
+    /// Follows the field from `seed`, against it if `sense` is negative.
+    fn trace(
+        &self,
+        lines: &mut Vec<Vec<Point>>,
+        seed: Point,
+        sense: f64,
+        channel: usize,
+        options: &Streamlines,
+    ) {
+        let mut line = vec![seed];
+        let mut position = seed;
+        let mut previous: Option<Point> = None;
+        let steps = (options.max_length / options.step).ceil() as u32;
+        for _ in 0..steps {
+            let Some(element) = self.get_from_point(position) else {
+                break;
+            };
+            let field = element.property(channel).field;
+            let magnitude = field.magnitude();
+            if !magnitude.is_normal() {
+                break;
+            }
+            let direction = field * (sense / magnitude);
+            // Crossing a source or a sink flips the field.
+            if previous.is_some_and(|p| p.x * direction.x + p.y * direction.y < 0.0) {
+                break;
+            }
+            let movement = direction * options.step;
+            let (next, speed) = self.move_in_universe(position, movement);
+            if next != position + movement {
+                // Teleported: the line goes on from the exit portal.
+                let done = std::mem::replace(&mut line, vec![next]);
+                if done.len() > 1 {
+                    lines.push(done);
+                }
+                previous = Some(speed.direction());
+            } else {
+                line.push(next);
+                previous = Some(direction);
+            }
+            position = next;
+        }
+        if line.len() > 1 {
+            lines.push(line);
+        }
+    }
+
+    /// Draws the field lines of `channel` over `img`.
+    pub fn draw_streamlines(&self, img: &mut RgbImage, channel: usize, options: &Streamlines) {
+        for line in self.streamlines(channel, options) {
+            for segment in line.windows(2) {
+                draw_stroke(img, segment[0], segment[1], &options.stroke, options.colour);
+            }
+        }
+    }
+}
@@ -0,0 +1,901 @@
+//! Declarative scene files.
+//!
+//! A scene is a TOML document describing the universe dimensions, the mass
+//! sources, the portal pairs and the particle parameters of a run:
+//!
+//! ```toml
+//! [universe]
+//! width = 539
+//! height = 539
+//!
+//! [graviton]
+//! step_size = 3.0
+//! quantity = 512
+//! life_span = 800
+//!
+//! [sub_graviton]
+//! step_size = 0.9
+//! quantity = 256
+//! life_span = 100
+//!
+//! [[masses]]
+//! shape = "circle"
+//! center = [269.5, 269.5]
+//! radius = 4.0
+//! value = 1.0
+//!
+//! [[portals]]
+//! a = [[179.5, 134.75], [359.5, 134.75]]
+//! b = [[179.5, 404.25], [359.5, 404.25]]
+//! ```
+//!
+//! `[[charges]]` take the same shapes as `[[masses]]`, with values of either sign.
+//!
+//! Other kinds of sources are declared as channels, each with its own field, then placed
+//! with `[[sources]]` naming their channel:
+//!
+//! ```toml
+//! [[channels]]
+//! name = "heat"
+//! coupling = -0.5
+//! # Optional, replacing the run's `[graviton]` and `[sub_graviton]` for this channel.
+//! graviton = { step_size = 2.0, quantity = 128, life_span = 400 }
+//!
+//! [[sources]]
+//! channel = "heat"
+//! shape = "point"
+//! at = [100.0, 50.0]
+//! value = 3.0
+//! ```
+//!
+//! Channel names also name the folders of their frames: only ASCII letters, digits, `_` and
+//! `-`, and none of the [reserved](crate::types::RESERVED_NAMES) ones.
+//!
+//! `mass` and `charge` are predefined channels, used by `[[masses]]` and `[[charges]]`, and
+//! added to the universe only if they hold sources, or if there would be no channel at all.
+//!
+//! A `[dynamics]` table makes the run dynamic: `[[bodies]]` move under the field of their
+//! channel (`mass` by default), which sources and bodies keep emitting:
+//!
+//! ```toml
+//! [dynamics]
+//! steps = 2000
+//! strength = 50.0
+//!
+//! [[bodies]]
+//! position = [200.0, 269.5]
+//! velocity = [0.0, 0.5]
+//! value = 1.0
+//! ```
+//!
+//! Particles leave every emitter at the same evenly spaced angles by default. For Monte
+//! Carlo runs, to be averaged, `[run]` can turn them by a random amount per emitter and step
+//! instead, and pick them from a low-discrepancy sequence, `"golden"` or `"halton"`:
+//!
+//! ```toml
+//! [run]
+//! emission = "rotated"
+//! seed = 42
+//! directions = "golden"
+//! ```
+//!
+//! or, with `emission = "random"`, draw every particle's angle independently.
+//!
+//! Setting `block_size` (a power of two) in `[universe]` stores it as coarse regions of
+//! that side. Sources and portals always lie in single cells, and `refinement` sets how far
+//! around them regions get subdivided as well.
+
+use crate::{
+    dynamics::Dynamics,
+    gravitons::{Directions, Emission, ParticleParameters},
+    types::{Body, Channel, Point, Portal, PortalSet, Universe},
+};
+
+use std::{
+    error::Error,
+    fmt::{Display, Formatter, Result as FmtResult},
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use serde::Deserialize;
+
+/// A universe ready to be simulated, plus how to run it.
+#[derive(Debug, Clone)]
+pub struct Scene {
+    pub universe: Universe,
+    /// Moving sources, only simulated if `settings.dynamics` is set.
+    pub bodies: Vec<Body>,
+    pub settings: RunSettings,
+}
+
+#[derive(Debug, Clone)]
+pub struct RunSettings {
+    pub graviton: ParticleParameters,
+    pub sub_graviton: ParticleParameters,
+    /// Folder receiving the rendered frames.
+    pub output: PathBuf,
+    pub frame_rate: u32,
+    pub dynamics: Option<Dynamics>,
+    pub emission: Emission,
+}
+
+#[derive(Debug)]
+pub enum SceneError {
+    Io(io::Error),
+    Parse(toml::de::Error),
+    /// A syntactically valid entry describing an impossible scene.
+    Invalid {
+        entry: String,
+        reason: String,
+    },
+}
+
+impl SceneError {
+    fn invalid(entry: impl Into<String>, reason: impl Into<String>) -> SceneError {
+        SceneError::Invalid {
+            entry: entry.into(),
+            reason: reason.into(),
+        }
+    }
+}
+
+impl Display for SceneError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        match self {
+            SceneError::Io(e) => write!(f, "could not read scene: {e}"),
+            SceneError::Parse(e) => write!(f, "could not parse scene: {e}"),
+            SceneError::Invalid { entry, reason } => write!(f, "invalid `{entry}`: {reason}"),
+        }
+    }
+}
+
+impl Error for SceneError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            SceneError::Io(e) => Some(e),
+            SceneError::Parse(e) => Some(e),
+            SceneError::Invalid { .. } => None,
+        }
+    }
+}
+
+impl From<io::Error> for SceneError {
+    fn from(value: io::Error) -> Self {
+        SceneError::Io(value)
+    }
+}
+impl From<toml::de::Error> for SceneError {
+    fn from(value: toml::de::Error) -> Self {
+        SceneError::Parse(value)
+    }
+}
+
+impl Scene {
+    pub fn load(path: impl AsRef<Path>) -> Result<Scene, SceneError> {
+        let text = fs::read_to_string(path)?;
+        Scene::parse(&text)
+    }
+
+    pub fn parse(text: &str) -> Result<Scene, SceneError> {
+        let file: SceneFile = toml::from_str(text)?;
+        file.build()
+    }
+}
+
+//* File layout
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SceneFile {
+    universe: UniverseEntry,
+    graviton: ParticleEntry,
+    sub_graviton: ParticleEntry,
+    #[serde(default)]
+    run: RunEntry,
+    #[serde(default)]
+    masses: Vec<SourceEntry>,
+    /// Like masses, but signed.
+    #[serde(default)]
+    charges: Vec<SourceEntry>,
+    #[serde(default)]
+    channels: Vec<ChannelEntry>,
+    /// Sources of any channel.
+    #[serde(default)]
+    sources: Vec<SourceEntry>,
+    #[serde(default)]
+    portals: Vec<PortalEntry>,
+    dynamics: Option<DynamicsEntry>,
+    #[serde(default)]
+    bodies: Vec<BodyEntry>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct UniverseEntry {
+    width: u32,
+    height: u32,
+    /// Side of the coarsest regions, a power of two; 1 for a dense grid.
+    #[serde(default = "default_block_size")]
+    block_size: u32,
+    /// Regions holding a source or crossed by a portal get subdivided, as well as those
+    /// whose centre is closer to one than this many times their side.
+    #[serde(default = "default_refinement")]
+    refinement: f64,
+}
+
+fn default_block_size() -> u32 {
+    1
+}
+fn default_refinement() -> f64 {
+    2.0
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ParticleEntry {
+    step_size: f64,
+    quantity: u32,
+    /// Distance travelled before dying, in cells.
+    life_span: u32,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields, default)]
+struct RunEntry {
+    output: PathBuf,
+    frame_rate: u32,
+    emission: EmissionEntry,
+    /// Seed of the rotated or random emission.
+    seed: Option<u64>,
+    /// Evenly spaced by default, unless random.
+    directions: Option<DirectionsEntry>,
+}
+
+impl Default for RunEntry {
+    fn default() -> Self {
+        RunEntry {
+            output: PathBuf::from("output"),
+            frame_rate: 30,
+            emission: EmissionEntry::Even,
+            seed: None,
+            directions: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum EmissionEntry {
+    Even,
+    Rotated,
+    Random,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum DirectionsEntry {
+    Even,
+    Golden,
+    Halton,
+}
+
+/// A source as written, with the fields of its shape. Its channel is only given in
+/// `[[sources]]`, the other tables implying it.
+#[derive(Debug, Deserialize)]
+#[serde(tag = "shape", rename_all = "lowercase", deny_unknown_fields)]
+enum SourceEntry {
+    Point {
+        at: [f64; 2],
+        value: f64,
+        channel: Option<String>,
+    },
+    Circle {
+        center: [f64; 2],
+        radius: f64,
+        value: f64,
+        channel: Option<String>,
+    },
+    Rectangle {
+        min: [f64; 2],
+        max: [f64; 2],
+        value: f64,
+        channel: Option<String>,
+    },
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ChannelEntry {
+    name: String,
+    coupling: f64,
+    graviton: Option<ParticleEntry>,
+    sub_graviton: Option<ParticleEntry>,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Shape {
+    Point { at: [f64; 2] },
+    Circle { center: [f64; 2], radius: f64 },
+    Rectangle { min: [f64; 2], max: [f64; 2] },
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct DynamicsEntry {
+    steps: u32,
+    /// Acceleration per unit of field.
+    strength: f64,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct BodyEntry {
+    position: [f64; 2],
+    #[serde(default)]
+    velocity: [f64; 2],
+    value: f64,
+    #[serde(default = "default_body_channel")]
+    channel: String,
+}
+
+fn default_body_channel() -> String {
+    "mass".to_string()
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PortalEntry {
+    a: [[f64; 2]; 2],
+    b: [[f64; 2]; 2],
+}
+
+//* Validation
+
+fn point([x, y]: [f64; 2]) -> Point {
+    Point { x, y }
+}
+
+impl SceneFile {
+    fn build(self) -> Result<Scene, SceneError> {
+        let UniverseEntry {
+            width,
+            height,
+            block_size,
+            refinement,
+        } = self.universe;
+        if width == 0 || height == 0 {
+            return Err(SceneError::invalid(
+                "universe",
+                format!("dimensions must be positive, got {width}×{height}"),
+            ));
+        }
+        if !block_size.is_power_of_two() {
+            return Err(SceneError::invalid(
+                "universe.block_size",
+                format!("must be a power of two, got {block_size}"),
+            ));
+        }
+        if !(refinement.is_finite() && refinement >= 0.0) {
+            return Err(SceneError::invalid(
+                "universe.refinement",
+                format!("must be a non-negative number, got {refinement}"),
+            ));
+        }
+
+        let mut channels = Vec::with_capacity(self.channels.len());
+        for (i, entry) in self.channels.iter().enumerate() {
+            channels.push(entry.build(&format!("channels[{i}]"), &channels)?);
+        }
+
+        // (channel, entry name, entry) of every source, shorthands first.
+        let masses = self.masses.iter().enumerate();
+        let masses = masses.map(|(i, entry)| (Some("mass"), format!("masses[{i}]"), entry));
+        let charges = self.charges.iter().enumerate();
+        let charges = charges.map(|(i, entry)| (Some("charge"), format!("charges[{i}]"), entry));
+        let others = self.sources.iter().enumerate();
+        let others = others.map(|(i, entry)| (None, format!("sources[{i}]"), entry));
+        let mut sources = Vec::new();
+        for (implied, name, entry) in masses.chain(charges).chain(others) {
+            let (shape, value, channel) = entry.parts();
+            let channel = match (implied, channel) {
+                (Some(implied), None) => implied,
+                (None, Some(channel)) => channel,
+                (Some(implied), Some(_)) => {
+                    return Err(SceneError::invalid(
+                        format!("{name}.channel"),
+                        format!("is always `{implied}` here, use `[[sources]]` for others"),
+                    ));
+                }
+                (None, None) => {
+                    return Err(SceneError::invalid(name, "missing field `channel`"));
+                }
+            };
+            let channel = channel_index(&mut channels, channel, &name)?;
+            if !value.is_finite() {
+                return Err(SceneError::invalid(name, "value must be finite"));
+            }
+            let cells = shape.cells(&name, width, height)?;
+            sources.push((channel, shape, value, cells));
+        }
+
+        let mut bodies = Vec::with_capacity(self.bodies.len());
+        for (i, entry) in self.bodies.iter().enumerate() {
+            bodies.push(entry.build(&format!("bodies[{i}]"), &mut channels, width, height)?);
+        }
+        let dynamics = match self.dynamics {
+            Some(DynamicsEntry { steps, strength }) => {
+                if steps == 0 {
+                    return Err(SceneError::invalid("dynamics.steps", "must be positive"));
+                }
+                if !strength.is_finite() {
+                    return Err(SceneError::invalid("dynamics.strength", "must be finite"));
+                }
+                Some(Dynamics { steps, strength })
+            }
+            None if !bodies.is_empty() => {
+                return Err(SceneError::invalid(
+                    "bodies",
+                    "only move in a dynamic run, which needs a `[dynamics]` table",
+                ));
+            }
+            None => None,
+        };
+        if channels.is_empty() {
+            channels.push(Channel::mass());
+        }
+
+        let mut portalsets = Vec::with_capacity(self.portals.len());
+        for (i, portals) in self.portals.iter().enumerate() {
+            let entry = format!("portals[{i}]");
+            let a = portal(&format!("{entry}.a"), portals.a, width, height)?;
+            let b = portal(&format!("{entry}.b"), portals.b, width, height)?;
+            portalsets.push(PortalSet::new(a, b));
+        }
+
+        let mut universe = Universe::adaptive(width, height, block_size, channels);
+        let mut source_cells = vec![false; width as usize * height as usize];
+        for (_, _, _, cells) in &sources {
+            for &(x, y) in cells {
+                source_cells[y as usize * width as usize + x as usize] = true;
+            }
+        }
+        universe.refine(|center, size| {
+            let half = size as f64 / 2.0;
+            let (min, max) = (center - (half, half), center + (half, half));
+            // Clipped to the universe, which the last blocks may stick out of.
+            let (x1, y1) = (
+                (max.x as usize).min(width as usize),
+                (max.y as usize).min(height as usize),
+            );
+            let (x0, y0) = ((min.x as usize).min(x1), min.y as usize);
+            let holds_source = (y0..y1).any(|y| {
+                source_cells[y * width as usize..][x0..x1]
+                    .iter()
+                    .any(|&cell| cell)
+            });
+            let crossed = || {
+                portalsets
+                    .iter()
+                    .flat_map(|PortalSet { a, b }| [a, b])
+                    .any(|p| crosses_square(p.point_a, p.point_b, min, max))
+            };
+            let distance = || {
+                sources
+                    .iter()
+                    .map(|(_, shape, _, _)| shape.distance(center))
+                    .chain(portalsets.iter().flat_map(|PortalSet { a, b }| {
+                        [a, b].map(|p| distance_to_segment(center, p.point_a, p.point_b))
+                    }))
+                    .fold(f64::INFINITY, f64::min)
+            };
+            holds_source || crossed() || distance() < size as f64 * refinement
+        });
+        for (channel, _, value, cells) in &sources {
+            for &(x, y) in cells {
+                let element = universe[(x, y)].element_mut().unwrap();
+                element.property_mut(*channel).value = *value;
+            }
+        }
+        for portalset in portalsets {
+            universe.add_portal_set(portalset);
+        }
+
+        let settings = RunSettings {
+            graviton: self.graviton.build("graviton")?,
+            sub_graviton: self.sub_graviton.build("sub_graviton")?,
+            frame_rate: match self.run.frame_rate {
+                0 => return Err(SceneError::invalid("run.frame_rate", "must be positive")),
+                rate => rate,
+            },
+            emission: self.run.emission()?,
+            output: self.run.output,
+            dynamics,
+        };
+        Ok(Scene {
+            universe,
+            bodies,
+            settings,
+        })
+    }
+}
+
+/// Index of the channel named `name`, adding it if predefined but not in use yet.
+fn channel_index(
+    channels: &mut Vec<Channel>,
+    name: &str,
+    entry: &str,
+) -> Result<usize, SceneError> {
+    if let Some(index) = channels.iter().position(|c| c.name == name) {
+        return Ok(index);
+    }
+    channels.push(match name {
+        "mass" => Channel::mass(),
+        "charge" => Channel::charge(),
+        _ => {
+            return Err(SceneError::invalid(
+                format!("{entry}.channel"),
+                format!("unknown channel `{name}`"),
+            ));
+        }
+    });
+    Ok(channels.len() - 1)
+}
+
+impl BodyEntry {
+    fn build(
+        &self,
+        entry: &str,
+        channels: &mut Vec<Channel>,
+        width: u32,
+        height: u32,
+    ) -> Result<Body, SceneError> {
+        let (position, velocity) = (point(self.position), point(self.velocity));
+        if !(position.x.is_finite() && position.y.is_finite()) {
+            return Err(SceneError::invalid(entry, "position must be finite"));
+        }
+        if !(0.0..width as f64).contains(&position.x) || !(0.0..height as f64).contains(&position.y)
+        {
+            return Err(SceneError::invalid(
+                entry,
+                format!("{position} is outside the {width}×{height} universe"),
+            ));
+        }
+        if !(velocity.x.is_finite() && velocity.y.is_finite()) {
+            return Err(SceneError::invalid(entry, "velocity must be finite"));
+        }
+        if !self.value.is_finite() {
+            return Err(SceneError::invalid(entry, "value must be finite"));
+        }
+        Ok(Body {
+            position,
+            velocity,
+            value: self.value,
+            channel: channel_index(channels, &self.channel, entry)?,
+        })
+    }
+}
+
+impl ChannelEntry {
+    fn build(&self, entry: &str, previous: &[Channel]) -> Result<Channel, SceneError> {
+        Channel::check_name(&self.name)
+            .map_err(|message| SceneError::invalid(format!("{entry}.name"), message))?;
+        if previous.iter().any(|c| c.name == self.name) {
+            return Err(SceneError::invalid(
+                format!("{entry}.name"),
+                format!("channel `{}` is already declared", self.name),
+            ));
+        }
+        if !self.coupling.is_finite() {
+            return Err(SceneError::invalid(
+                format!("{entry}.coupling"),
+                "must be finite",
+            ));
+        }
+        let mut channel = Channel::new(&self.name, self.coupling);
+        if let Some(graviton) = &self.graviton {
+            channel.graviton = Some(graviton.build(&format!("{entry}.graviton"))?);
+        }
+        if let Some(sub_graviton) = &self.sub_graviton {
+            channel.sub_graviton = Some(sub_graviton.build(&format!("{entry}.sub_graviton"))?);
+        }
+        Ok(channel)
+    }
+}
+
+impl ParticleEntry {
+    fn build(&self, entry: &str) -> Result<ParticleParameters, SceneError> {
+        if !(self.step_size.is_finite() && self.step_size > 0.0) {
+            return Err(SceneError::invalid(
+                format!("{entry}.step_size"),
+                format!("must be a positive number, got {}", self.step_size),
+            ));
+        }
+        if self.quantity == 0 {
+            return Err(SceneError::invalid(
+                format!("{entry}.quantity"),
+                "must be positive",
+            ));
+        }
+        if self.life_span == 0 {
+            return Err(SceneError::invalid(
+                format!("{entry}.life_span"),
+                "must be positive",
+            ));
+        }
+        Ok(ParticleParameters::new(
+            self.step_size,
+            self.quantity,
+            self.life_span,
+        ))
+    }
+}
+
+impl RunEntry {
+    fn emission(&self) -> Result<Emission, SceneError> {
+        let directions = match self.directions {
+            None | Some(DirectionsEntry::Even) => Directions::Even,
+            Some(DirectionsEntry::Golden) => Directions::GoldenAngle,
+            Some(DirectionsEntry::Halton) => Directions::Halton,
+        };
+        match (self.emission, self.seed) {
+            (EmissionEntry::Even, Some(_)) => Err(SceneError::invalid(
+                "run.seed",
+                "only used with `emission = \"rotated\"` or `\"random\"`",
+            )),
+            (EmissionEntry::Even, None) => Ok(Emission {
+                directions,
+                seed: None,
+            }),
+            (EmissionEntry::Rotated, seed) => Ok(Emission {
+                directions,
+                seed: Some(seed.unwrap_or(0)),
+            }),
+            (EmissionEntry::Random, _) if self.directions.is_some() => Err(SceneError::invalid(
+                "run.directions",
+                "can't be set with `emission = \"random\"`, which draws every angle",
+            )),
+            (EmissionEntry::Random, seed) => Ok(Emission {
+                directions: Directions::Random,
+                seed: Some(seed.unwrap_or(0)),
+            }),
+        }
+    }
+}
+
+impl SourceEntry {
+    fn parts(&self) -> (Shape, f64, Option<&str>) {
+        match self {
+            SourceEntry::Point { at, value, channel } => {
+                (Shape::Point { at: *at }, *value, channel.as_deref())
+            }
+            SourceEntry::Circle {
+                center,
+                radius,
+                value,
+                channel,
+            } => (
+                Shape::Circle {
+                    center: *center,
+                    radius: *radius,
+                },
+                *value,
+                channel.as_deref(),
+            ),
+            SourceEntry::Rectangle {
+                min,
+                max,
+                value,
+                channel,
+            } => (
+                Shape::Rectangle {
+                    min: *min,
+                    max: *max,
+                },
+                *value,
+                channel.as_deref(),
+            ),
+        }
+    }
+}
+
+impl Shape {
+    fn distance(&self, p: Point) -> f64 {
+        match *self {
+            Shape::Point { at } => (p - point(at)).magnitude(),
+            Shape::Circle { center, radius } => ((p - point(center)).magnitude() - radius).max(0.0),
+            Shape::Rectangle { min, max } => {
+                let dx = (min[0] - p.x).max(p.x - max[0]).max(0.0);
+                let dy = (min[1] - p.y).max(p.y - max[1]).max(0.0);
+                dx.hypot(dy)
+            }
+        }
+    }
+
+    /// Cells covered by the shape, failing if any of them is outside the universe.
+    fn cells(&self, entry: &str, width: u32, height: u32) -> Result<Vec<(u32, u32)>, SceneError> {
+        let mut cells = Vec::new();
+        match *self {
+            Shape::Point { at } => cells.push((at[0].floor() as i64, at[1].floor() as i64)),
+            Shape::Circle { center, radius } => {
+                if !(radius.is_finite() && radius >= 0.0) {
+                    return Err(SceneError::invalid(
+                        entry,
+                        format!("radius must be a non-negative number, got {radius}"),
+                    ));
+                }
+                for y in (-radius) as i64..=radius as i64 {
+                    for x in (-radius) as i64..=radius as i64 {
+                        if (x as f64).hypot(y as f64) <= radius {
+                            cells.push((center[0] as i64 + x, center[1] as i64 + y));
+                        }
+                    }
+                }
+            }
+            Shape::Rectangle { min, max } => {
+                if min[0] > max[0] || min[1] > max[1] {
+                    return Err(SceneError::invalid(
+                        entry,
+                        "`min` must not be greater than `max`",
+                    ));
+                }
+                for y in min[1].floor() as i64..max[1].ceil() as i64 {
+                    for x in min[0].floor() as i64..max[0].ceil() as i64 {
+                        cells.push((x, y));
+                    }
+                }
+            }
+        }
+        if let Some((x, y)) = cells
+            .iter()
+            .find(|(x, y)| !(0..width as i64).contains(x) || !(0..height as i64).contains(y))
+        {
+            return Err(SceneError::invalid(
+                entry,
+                format!("cell ({x}, {y}) is outside the {width}×{height} universe"),
+            ));
+        }
+        Ok(cells
+            .into_iter()
+            .map(|(x, y)| (x as u32, y as u32))
+            .collect())
+    }
+}
+
+fn distance_to_segment(p: Point, a: Point, b: Point) -> f64 {
+    let ab = b - a;
+    let t = ((p.x - a.x) * ab.x + (p.y - a.y) * ab.y) / ab.magnitude_2();
+    (p - (a + ab * t.clamp(0.0, 1.0))).magnitude()
+}
+
+/// Whether the segment from `a` to `b` touches the square from `min` to `max`: their
+/// bounding boxes overlap, and the square's corners aren't all on one side of the line.
+fn crosses_square(a: Point, b: Point, min: Point, max: Point) -> bool {
+    if a.x.max(b.x) < min.x || a.x.min(b.x) > max.x || a.y.max(b.y) < min.y || a.y.min(b.y) > max.y
+    {
+        return false;
+    }
+    let ab = b - a;
+    let side = |x: f64, y: f64| ab.x * (y - a.y) - ab.y * (x - a.x);
+    let sides = [
+        side(min.x, min.y),
+        side(max.x, min.y),
+        side(min.x, max.y),
+        side(max.x, max.y),
+    ];
+    !(sides.iter().all(|&s| s > 0.0) || sides.iter().all(|&s| s < 0.0))
+}
+
+fn portal(
+    entry: &str,
+    [a, b]: [[f64; 2]; 2],
+    width: u32,
+    height: u32,
+) -> Result<Portal, SceneError> {
+    let (a, b) = (point(a), point(b));
+    for p in [a, b] {
+        if !(p.x.is_finite() && p.y.is_finite()) {
+            return Err(SceneError::invalid(entry, "coordinates must be finite"));
+        }
+        if !(0.0..=width as f64).contains(&p.x) || !(0.0..=height as f64).contains(&p.y) {
+            return Err(SceneError::invalid(
+                entry,
+                format!("{p} is outside the {width}×{height} universe"),
+            ));
+        }
+    }
+    let portal = Portal::new(a, b);
+    if portal.size() == 0.0 {
+        return Err(SceneError::invalid(entry, "endpoints must be distinct"));
+    }
+    Ok(portal)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A scene of a 50×50 universe, with `universe` added to its table, followed by `rest`.
+    fn parse(universe: &str, rest: &str) -> Result<Scene, SceneError> {
+        Scene::parse(&format!(
+            "[universe]\nwidth = 50\nheight = 50\n{universe}\n\
+             [graviton]\nstep_size = 1.0\nquantity = 8\nlife_span = 10\n\
+             [sub_graviton]\nstep_size = 1.0\nquantity = 8\nlife_span = 5\n\
+             {rest}"
+        ))
+    }
+
+    fn coarse(refinement: f64) -> String {
+        format!("block_size = 16\nrefinement = {refinement}")
+    }
+
+    #[test]
+    fn sources_keep_their_cell_in_coarse_blocks() {
+        let masses = "[[masses]]\nshape = \"point\"\nat = [20.5, 37.5]\nvalue = 1.0\n\
+                      [[masses]]\nshape = \"point\"\nat = [22.5, 38.5]\nvalue = 2.0\n";
+        for refinement in [0.0, 0.01, 0.5, 2.0, 8.0] {
+            let universe = parse(&coarse(refinement), masses).unwrap().universe;
+            let mut total = 0.0;
+            universe.for_each_element(|origin, size, element| {
+                total += element.property(0).value * universe.area(origin, size);
+            });
+            assert_eq!(total, 3.0, "refinement {refinement}");
+            assert_eq!(universe[(20, 37)].element().unwrap().property(0).value, 1.0);
+            assert_eq!(universe[(22, 38)].element().unwrap().property(0).value, 2.0);
+        }
+    }
+
+    #[test]
+    fn portals_lie_in_single_cells() {
+        let portals = "[[portals]]\na = [[3.0, 10.5], [45.0, 10.5]]\n\
+                       b = [[10.5, 20.0], [10.5, 40.0]]\n";
+        let universe = parse(&coarse(0.0), portals).unwrap().universe;
+        for x in 3..45 {
+            let mut size = 0;
+            universe.for_each_element(|origin, side, _| {
+                if origin == (x, 10) {
+                    size = side;
+                }
+            });
+            assert_eq!(size, 1, "cell ({x}, 10)");
+        }
+        // Far from both, blocks stay whole.
+        assert!(universe.element_count() < 50 * 50 / 2);
+    }
+
+    #[test]
+    fn sources_reject_unknown_and_misplaced_keys() {
+        for source in [
+            "[[masses]]\nshape = \"point\"\nat = [3.0, 4.0]\nvalue = 1.0\nradius = 5.0\n",
+            "[[masses]]\nshape = \"point\"\nat = [3.0, 4.0]\nvalue = 1.0\nchannel = \"charge\"\n",
+            "[[charges]]\nshape = \"circle\"\ncenter = [3.0, 4.0]\nradius = 1.0\nvalu = 1.0\n",
+            "[[sources]]\nshape = \"point\"\nat = [3.0, 4.0]\nvalue = 1.0\n",
+        ] {
+            assert!(parse("", source).is_err(), "{source}");
+        }
+        let source =
+            "[[sources]]\nshape = \"point\"\nat = [3.0, 4.0]\nvalue = 1.0\nchannel = \"charge\"\n";
+        let universe = parse("", source).unwrap().universe;
+        assert_eq!(universe.channel_index("charge"), Some(0));
+    }
+
+    #[test]
+    fn channels_reject_names_unfit_for_paths() {
+        for name in [
+            "",
+            "..",
+            "../x",
+            "/home/u/pics",
+            "a b",
+            "heat.png",
+            "arrays",
+            "final",
+        ] {
+            let channel = format!("[[channels]]\nname = \"{name}\"\ncoupling = 1.0\n");
+            assert!(parse("", &channel).is_err(), "{name}");
+        }
+        let channel = "[[channels]]\nname = \"heat_2-b\"\ncoupling = 1.0\n";
+        let universe = parse("", channel).unwrap().universe;
+        assert!(universe.channel_index("heat_2-b").is_some());
+    }
+}
@@ -0,0 +1,228 @@
+//! Step-by-step driver of a graviton simulation.
+
+use crate::{
+    dynamics::{self, Dynamics},
+    gravitons::{self, Emission, ParticleParameters},
+    types::{Body, Particle, Point, Universe},
+};
+
+/// A universe whose field is being built by gravitons.
+///
+/// Spawning happens on creation, then every [`Simulation::step`] moves the gravitons and
+/// lets them deposit their field, until they die after `graviton.life_span` steps.
+/// `graviton` and `sub_graviton` apply to the channels not setting their own.
+///
+/// In [dynamic](Simulation::dynamic) mode, sources and bodies emit gravitons at every step
+/// instead, and the field only holds the deposits of the last step. Bodies don't feel
+/// their own field, not even through portals.
+///
+/// Particles leave at the same angles from every emitter, unless given another
+/// [emission](Simulation::with_emission).
+#[derive(Debug, Clone)]
+pub struct Simulation {
+    pub universe: Universe,
+    pub graviton: ParticleParameters,
+    pub sub_graviton: ParticleParameters,
+    gravitons: Box<[Particle]>,
+    bodies: Vec<Body>,
+    dynamics: Option<Dynamics>,
+    emission: Emission,
+    step: u32,
+}
+
+impl Simulation {
+    pub fn new(
+        universe: Universe,
+        graviton: ParticleParameters,
+        sub_graviton: ParticleParameters,
+    ) -> Simulation {
+        let mut simulation = Simulation {
+            universe,
+            graviton,
+            sub_graviton,
+            gravitons: Box::default(),
+            bodies: Vec::new(),
+            dynamics: None,
+            emission: Emission::default(),
+            step: 0,
+        };
+        simulation.emit();
+        simulation
+    }
+
+    /// A simulation where `bodies` move under the field, for `dynamics.steps` steps.
+    pub fn dynamic(
+        universe: Universe,
+        graviton: ParticleParameters,
+        sub_graviton: ParticleParameters,
+        bodies: Vec<Body>,
+        dynamics: Dynamics,
+    ) -> Simulation {
+        let mut simulation = Simulation {
+            universe,
+            graviton,
+            sub_graviton,
+            gravitons: Box::default(),
+            bodies,
+            dynamics: Some(dynamics),
+            emission: Emission::default(),
+            step: 0,
+        };
+        simulation.emit();
+        simulation
+    }
+
+    /// Emits the gravitons following `emission` instead, from now on.
+    ///
+    /// # Panics
+    ///
+    /// If the simulation already started: its first gravitons are emitted again.
+    pub fn with_emission(mut self, emission: Emission) -> Simulation {
+        assert_eq!(self.step, 0, "emission must be set before the first step");
+        if emission != self.emission {
+            self.emission = emission;
+            self.gravitons = Box::default();
+            self.emit();
+        }
+        self
+    }
+
+    /// Adds gravitons leaving every source and body.
+    fn emit(&mut self) {
+        let parameters = self.graviton_parameters();
+        let mut gravitons = std::mem::take(&mut self.gravitons).into_vec();
+        let (emission, step) = (self.emission, self.step);
+        gravitons.extend(gravitons::spawn(
+            &mut self.universe,
+            &parameters,
+            emission,
+            step,
+        ));
+        gravitons.extend(gravitons::spawn_from_bodies(
+            &self.universe,
+            &self.bodies,
+            &parameters,
+            emission,
+            step,
+        ));
+        self.gravitons = gravitons.into();
+    }
+
+    /// Graviton parameters of every channel.
+    pub fn graviton_parameters(&self) -> Vec<ParticleParameters> {
+        let channels = self.universe.channels().iter();
+        channels
+            .map(|c| c.graviton.unwrap_or(self.graviton))
+            .collect()
+    }
+    /// Sub-graviton parameters of every channel.
+    pub fn sub_graviton_parameters(&self) -> Vec<ParticleParameters> {
+        let channels = self.universe.channels().iter();
+        channels
+            .map(|c| c.sub_graviton.unwrap_or(self.sub_graviton))
+            .collect()
+    }
+
+    /// Steps done so far.
+    pub fn step_index(&self) -> u32 {
+        self.step
+    }
+
+    /// Steps to be done in total: until the longest-lived gravitons die, unless dynamic.
+    pub fn steps(&self) -> u32 {
+        if let Some(dynamics) = self.dynamics {
+            return dynamics.steps;
+        }
+        let parameters = self.graviton_parameters();
+        parameters.iter().map(|p| p.life_span).max().unwrap_or(0)
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.step >= self.steps()
+    }
+
+    /// Gravitons still alive.
+    pub fn gravitons(&self) -> &[Particle] {
+        &self.gravitons
+    }
+
+    /// Moving bodies, empty unless dynamic.
+    pub fn bodies(&self) -> &[Body] {
+        &self.bodies
+    }
+
+    pub fn dynamics(&self) -> Option<Dynamics> {
+        self.dynamics
+    }
+
+    pub fn emission(&self) -> Emission {
+        self.emission
+    }
+
+    /// A simulation in the middle of its run, as saved by a checkpoint.
+    pub(crate) fn restore(
+        universe: Universe,
+        (graviton, sub_graviton): (ParticleParameters, ParticleParameters),
+        gravitons: Box<[Particle]>,
+        bodies: Vec<Body>,
+        dynamics: Option<Dynamics>,
+        emission: Emission,
+        step: u32,
+    ) -> Simulation {
+        Simulation {
+            universe,
+            graviton,
+            sub_graviton,
+            gravitons,
+            bodies,
+            dynamics,
+            emission,
+            step,
+        }
+    }
+
+    /// Advances the gravitons once, returning `false` if the simulation was already over.
+    pub fn step(&mut self) -> bool {
+        if self.is_finished() {
+            return false;
+        }
+        if self.dynamics.is_some() {
+            self.universe.clear_fields();
+        }
+        let sub_gravitons = self.sub_graviton_parameters();
+        let mut own_fields = vec![Point::default(); self.bodies.len()];
+        let gravitons = gravitons::advance(
+            &mut self.universe,
+            &mut self.gravitons,
+            &self.bodies,
+            &mut own_fields,
+            &sub_gravitons,
+            self.emission,
+            self.step,
+        );
+        self.step += 1;
+        // Channels may have shorter-lived gravitons than others.
+        let life_spans: Vec<u32> = self
+            .graviton_parameters()
+            .iter()
+            .map(|p| p.life_span)
+            .collect();
+        let mut gravitons = gravitons.into_vec();
+        gravitons.retain(|g| g.age < life_spans[g.channel]);
+        self.gravitons = gravitons.into();
+        if let Some(dynamics) = self.dynamics {
+            dynamics::move_bodies(
+                &self.universe,
+                &mut self.bodies,
+                &own_fields,
+                dynamics.strength,
+            );
+            self.emit();
+        }
+        true
+    }
+
+    pub fn into_universe(self) -> Universe {
+        self.universe
+    }
+}
@@ -0,0 +1,338 @@
+//! Binary snapshots of a universe's fields, to re-render them later.
+//!
+//! All numbers are little-endian:
+//!
+//! | field        | type                                                 |
+//! |--------------|------------------------------------------------------|
+//! | magic        | `b"FLUXSNAP"`                                        |
+//! | version      | `u32`                                                |
+//! | width/height | `u32`, `u32`, positive, at most 2³² cells in all     |
+//! | block size   | `u32`                                                |
+//! | channels     | `u32` count, then each channel, see below            |
+//! | portal sets  | `u32` count, then 8 × `f64` per set                  |
+//! | blocks       | row-major regions, see below                         |
+//!
+//! A channel is its name as a `u32` byte length, at most 1024, and UTF-8 bytes, its coupling
+//! as `f64`, then its graviton and sub-graviton parameters: a `u8` set to `1` if present,
+//! followed by `step_size` as `f64`, `quantity` and `life_span` as `u32`.
+//!
+//! A region is a `u8` tag: `0` followed by the element's properties, or `1` followed by its
+//! four quadrants. Properties are, for each channel, `value`, `field.x` and `field.y` as
+//! `f64`.
+
+use crate::{
+    gravitons::ParticleParameters,
+    types::{Channel, Element, Point, Portal, PortalSet, Property, Region, Universe},
+};
+
+use std::{
+    fs::File,
+    io::{self, BufReader, BufWriter, Read, Write},
+    path::Path,
+};
+
+const MAGIC: &[u8; 8] = b"FLUXSNAP";
+const VERSION: u32 = 4;
+const ELEMENT: u8 = 0;
+const SUBDIVISION: u8 = 1;
+/// Longest channel name read, in bytes: a corrupt length mustn't allocate gigabytes.
+const MAX_NAME: u32 = 1024;
+/// Largest universe read, in cells: 65536×65536.
+const MAX_CELLS: u64 = 1 << 32;
+
+pub fn save_snapshot(universe: &Universe, path: impl AsRef<Path>) -> io::Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    write_universe(&mut writer, universe)?;
+    writer.flush()
+}
+
+pub fn load_snapshot(path: impl AsRef<Path>) -> io::Result<Universe> {
+    let mut reader = BufReader::new(File::open(path)?);
+    read_universe(&mut reader)
+}
+
+pub fn write_universe(writer: &mut impl Write, universe: &Universe) -> io::Result<()> {
+    writer.write_all(MAGIC)?;
+    write_u32(writer, VERSION)?;
+    write_u32(writer, universe.width)?;
+    write_u32(writer, universe.height)?;
+    write_u32(writer, universe.block_size())?;
+
+    write_u32(writer, universe.channels().len() as u32)?;
+    for channel in universe.channels() {
+        write_channel(writer, channel)?;
+    }
+
+    write_u32(writer, universe.portals().len() as u32)?;
+    for PortalSet { a, b } in universe.portals() {
+        for point in [a.point_a, a.point_b, b.point_a, b.point_b] {
+            write_point(writer, point)?;
+        }
+    }
+
+    for block in universe.blocks() {
+        write_region(writer, block)?;
+    }
+    Ok(())
+}
+
+fn write_region(writer: &mut impl Write, region: &Region) -> io::Result<()> {
+    match region {
+        Region::Element(element) => {
+            writer.write_all(&[ELEMENT])?;
+            for property in &element.properties {
+                write_f64(writer, property.value)?;
+                write_point(writer, property.field)?;
+            }
+            Ok(())
+        }
+        Region::Subdivision(quadrants) => {
+            writer.write_all(&[SUBDIVISION])?;
+            quadrants.iter().try_for_each(|q| write_region(writer, q))
+        }
+    }
+}
+
+fn write_channel(writer: &mut impl Write, channel: &Channel) -> io::Result<()> {
+    write_u32(writer, channel.name.len() as u32)?;
+    writer.write_all(channel.name.as_bytes())?;
+    write_f64(writer, channel.coupling)?;
+    for parameters in [channel.graviton, channel.sub_graviton] {
+        match parameters {
+            Some(parameters) => {
+                writer.write_all(&[1])?;
+                write_parameters(writer, &parameters)?;
+            }
+            None => writer.write_all(&[0])?,
+        }
+    }
+    Ok(())
+}
+
+pub fn read_universe(reader: &mut impl Read) -> io::Result<Universe> {
+    let mut magic = [0; 8];
+    reader.read_exact(&mut magic)?;
+    if &magic != MAGIC {
+        return Err(invalid_data("not a field snapshot"));
+    }
+    let version = read_u32(reader)?;
+    if version != VERSION {
+        return Err(invalid_data(format!(
+            "unsupported snapshot version {version}"
+        )));
+    }
+    let width = read_u32(reader)?;
+    let height = read_u32(reader)?;
+    if width == 0 || height == 0 || width as u64 * height as u64 > MAX_CELLS {
+        return Err(invalid_data(format!("invalid size {width}×{height}")));
+    }
+    let block_size = read_u32(reader)?;
+    if !block_size.is_power_of_two() {
+        return Err(invalid_data(format!("invalid block size {block_size}")));
+    }
+    let channels: Vec<_> = (0..read_u32(reader)?)
+        .map(|_| read_channel(reader))
+        .collect::<io::Result<_>>()?;
+
+    let portals: Vec<_> = (0..read_u32(reader)?)
+        .map(|_| {
+            let a = Portal::new(read_point(reader)?, read_point(reader)?);
+            let b = Portal::new(read_point(reader)?, read_point(reader)?);
+            Ok(PortalSet::new(a, b))
+        })
+        .collect::<io::Result<_>>()?;
+
+    // Read before allocating, so that a truncated file can't claim more than it holds.
+    let blocks = (0..Universe::block_count(width, height, block_size))
+        .map(|_| read_region(reader, block_size, channels.len()))
+        .collect::<io::Result<_>>()?;
+    let mut universe = Universe::from_blocks(width, height, block_size, channels, blocks);
+    for portals in portals {
+        universe.add_portal_set(portals);
+    }
+    Ok(universe)
+}
+
+fn read_region(reader: &mut impl Read, size: u32, channels: usize) -> io::Result<Region> {
+    let mut tag = [0];
+    reader.read_exact(&mut tag)?;
+    match tag[0] {
+        ELEMENT => {
+            let mut element = Element::new(channels);
+            for property in &mut element.properties {
+                *property = Property {
+                    value: read_f64(reader)?,
+                    field: read_point(reader)?,
+                };
+            }
+            Ok(Region::Element(element))
+        }
+        SUBDIVISION if size > 1 => Ok(Region::Subdivision(
+            (0..4)
+                .map(|_| read_region(reader, size / 2, channels))
+                .collect::<io::Result<_>>()?,
+        )),
+        SUBDIVISION => Err(invalid_data("subdivided single cell")),
+        tag => Err(invalid_data(format!("unknown region tag {tag}"))),
+    }
+}
+
+fn read_channel(reader: &mut impl Read) -> io::Result<Channel> {
+    let length = read_u32(reader)?;
+    if length > MAX_NAME {
+        return Err(invalid_data(format!("channel name of {length} bytes")));
+    }
+    let mut name = vec![0; length as usize];
+    reader.read_exact(&mut name)?;
+    let name = String::from_utf8(name).map_err(|_| invalid_data("channel name isn't UTF-8"))?;
+    Channel::check_name(&name).map_err(|e| invalid_data(format!("channel name {e}")))?;
+    let mut channel = Channel::new(name, read_f64(reader)?);
+    for parameters in [&mut channel.graviton, &mut channel.sub_graviton] {
+        let mut present = [0];
+        reader.read_exact(&mut present)?;
+        if present[0] != 0 {
+            *parameters = Some(read_parameters(reader)?);
+        }
+    }
+    Ok(channel)
+}
+
+pub(crate) fn invalid_data(message: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.into())
+}
+
+pub(crate) fn write_u32(writer: &mut impl Write, value: u32) -> io::Result<()> {
+    writer.write_all(&value.to_le_bytes())
+}
+pub(crate) fn write_f64(writer: &mut impl Write, value: f64) -> io::Result<()> {
+    writer.write_all(&value.to_le_bytes())
+}
+pub(crate) fn write_point(writer: &mut impl Write, point: Point) -> io::Result<()> {
+    write_f64(writer, point.x)?;
+    write_f64(writer, point.y)
+}
+
+pub(crate) fn write_parameters(
+    writer: &mut impl Write,
+    parameters: &ParticleParameters,
+) -> io::Result<()> {
+    write_f64(writer, parameters.step_size)?;
+    write_u32(writer, parameters.quantity)?;
+    write_u32(writer, parameters.life_span)
+}
+
+pub(crate) fn read_u32(reader: &mut impl Read) -> io::Result<u32> {
+    let mut bytes = [0; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+pub(crate) fn read_f64(reader: &mut impl Read) -> io::Result<f64> {
+    let mut bytes = [0; 8];
+    reader.read_exact(&mut bytes)?;
+    Ok(f64::from_le_bytes(bytes))
+}
+pub(crate) fn read_point(reader: &mut impl Read) -> io::Result<Point> {
+    Ok(Point {
+        x: read_f64(reader)?,
+        y: read_f64(reader)?,
+    })
+}
+pub(crate) fn read_parameters(reader: &mut impl Read) -> io::Result<ParticleParameters> {
+    Ok(ParticleParameters {
+        step_size: read_f64(reader)?,
+        quantity: read_u32(reader)?,
+        life_span: read_u32(reader)?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A refined universe of two channels, with a portal set and every field set.
+    fn universe() -> Universe {
+        let mut charge = Channel::charge();
+        charge.graviton = Some(ParticleParameters::new(0.5, 12, 7));
+        let mut universe = Universe::adaptive(20, 12, 8, vec![Channel::mass(), charge]);
+        universe.refine(|center, size| size > 1 && center.x < 8.0 && center.y < 8.0);
+        let portal = |x0, y0, x1, y1| Portal::new(Point { x: x0, y: y0 }, Point { x: x1, y: y1 });
+        universe.add_portal_set(PortalSet::new(
+            portal(2.0, 9.0, 6.0, 9.0),
+            portal(14.0, 3.0, 14.0, 7.0),
+        ));
+        let mut i = 0.0;
+        universe.for_each_element_mut(|_, _, element| {
+            for property in &mut element.properties {
+                i += 1.0;
+                *property = Property {
+                    value: i,
+                    field: Point { x: -i, y: i / 3.0 },
+                };
+            }
+        });
+        universe
+    }
+
+    fn bytes(universe: &Universe) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        write_universe(&mut bytes, universe).unwrap();
+        bytes
+    }
+
+    #[test]
+    fn round_trips() {
+        let universe = universe();
+        let written = bytes(&universe);
+        let read = read_universe(&mut &written[..]).unwrap();
+        assert_eq!((read.width, read.height), (universe.width, universe.height));
+        assert_eq!(read.block_size(), universe.block_size());
+        assert_eq!(read.channels(), universe.channels());
+        assert_eq!(read.portals(), universe.portals());
+        let regions = |universe: &Universe| {
+            let mut regions = Vec::new();
+            universe.for_each_element(|origin, size, element| {
+                regions.push((origin, size, element.properties.clone()));
+            });
+            regions
+        };
+        assert_eq!(regions(&read), regions(&universe));
+        assert_eq!(bytes(&read), written);
+    }
+
+    #[test]
+    fn rejects_corrupt_snapshots() {
+        let bytes = bytes(&universe());
+        let error = |bytes: &[u8]| read_universe(&mut &bytes[..]).unwrap_err().kind();
+        assert_eq!(
+            error(&bytes[..bytes.len() - 1]),
+            io::ErrorKind::UnexpectedEof
+        );
+        let mut version = bytes.clone();
+        version[8] += 1;
+        assert_eq!(error(&version), io::ErrorKind::InvalidData);
+        // The first channel's name length follows the 8 magic bytes and 5 `u32`.
+        let mut name = bytes.clone();
+        name[28..32].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert_eq!(error(&name), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn rejects_corrupt_headers() {
+        let bytes = bytes(&universe());
+        let error = |bytes: &[u8]| read_universe(&mut &bytes[..]).unwrap_err().kind();
+        let with_size = |width: u32, height: u32| {
+            let mut bytes = bytes.clone();
+            bytes[12..16].copy_from_slice(&width.to_le_bytes());
+            bytes[16..20].copy_from_slice(&height.to_le_bytes());
+            bytes
+        };
+        assert_eq!(error(&bytes[..14]), io::ErrorKind::UnexpectedEof);
+        for (width, height) in [(0, 12), (20, 0), (u32::MAX, u32::MAX), (1 << 17, 1 << 16)] {
+            assert_eq!(error(&with_size(width, height)), io::ErrorKind::InvalidData);
+        }
+        // As large as allowed, but without the blocks to fill it: fails without allocating.
+        let mut large = with_size(1 << 16, 1 << 16);
+        large[20..24].copy_from_slice(&1u32.to_le_bytes());
+        assert!(read_universe(&mut &large[..]).is_err());
+    }
+}
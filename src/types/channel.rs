@@ -0,0 +1,60 @@
+use crate::gravitons::ParticleParameters;
+
+/// A named kind of source, with the vector field it emits.
+///
+/// Every element of a universe holds one [`Property`](super::Property) per channel, in the
+/// order of [`Universe::channels`](super::Universe::channels).
+#[derive(Debug, Clone, PartialEq)]
+pub struct Channel {
+    pub name: String,
+    /// Factor of the field left by a sub-graviton, relative to its direction: negative
+    /// fields point towards positive sources.
+    pub coupling: f64,
+    /// Emission parameters replacing the simulation's for this channel.
+    pub graviton: Option<ParticleParameters>,
+    pub sub_graviton: Option<ParticleParameters>,
+}
+
+impl Channel {
+    pub fn new(name: impl Into<String>, coupling: f64) -> Channel {
+        Channel {
+            name: name.into(),
+            coupling,
+            graviton: None,
+            sub_graviton: None,
+        }
+    }
+
+    /// Positive sources, whose field points towards them.
+    pub fn mass() -> Channel {
+        Channel::new("mass", -1.0)
+    }
+
+    /// Signed sources, whose field points away from positive charges: like charges repel.
+    pub fn charge() -> Channel {
+        Channel::new("charge", 1.0)
+    }
+
+    /// Checks that `name` can name files and folders: made of ASCII letters, digits, `_` and
+    /// `-`, and none of [`RESERVED_NAMES`].
+    pub fn check_name(name: &str) -> Result<(), String> {
+        let allowed = |c: char| c.is_ascii_alphanumeric() || c == '_' || c == '-';
+        if name.is_empty() {
+            Err("must not be empty".into())
+        } else if !name.chars().all(allowed) {
+            Err(format!(
+                "`{name}` must only hold ASCII letters, digits, `_` and `-`"
+            ))
+        } else if RESERVED_NAMES.contains(&name) {
+            Err(format!(
+                "`{name}` is reserved for the simulator's own output"
+            ))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// Names of the folders the simulator creates next to those of the channels' frames: the
+/// NumPy arrays of a run, and those exported from its snapshots.
+pub const RESERVED_NAMES: &[&str] = &["arrays", "final", "reference", "poisson"];
@@ -1,7 +1,9 @@
+mod channel;
 mod point;
 mod portal;
 mod universe;
 pub use self::{
+    channel::{Channel, RESERVED_NAMES},
     point::Point,
     portal::{Portal, PortalSet},
     universe::Universe,
@@ -13,15 +15,32 @@ pub struct Property {
     pub field: Point,
 }
 
-#[derive(Debug, Clone, Default, Copy, PartialEq)]
+/// Content of a region: one property per channel of the universe.
+#[derive(Debug, Clone, Default, PartialEq)]
 pub struct Element {
-    pub mass: Property,
+    pub properties: Box<[Property]>,
+}
+
+impl Element {
+    pub fn new(channels: usize) -> Element {
+        Element {
+            properties: vec![Property::default(); channels].into(),
+        }
+    }
+
+    pub fn property(&self, channel: usize) -> &Property {
+        &self.properties[channel]
+    }
+    pub fn property_mut(&mut self, channel: usize) -> &mut Property {
+        &mut self.properties[channel]
+    }
 }
 
 #[derive(Debug, Clone)]
 pub enum Region {
     Element(Element),
-    // Subdivision(Regions),
+    /// Quadrants of a square region: top-left, top-right, bottom-left and bottom-right.
+    Subdivision(Regions),
 }
 
 impl Default for Region {
@@ -31,33 +50,138 @@ impl Default for Region {
 }
 
 impl Region {
-    pub fn element(&self) -> Option<Element> {
+    pub fn element(&self) -> Option<&Element> {
         match self {
-            Region::Element(e) => Some(*e),
-            // _ => None,
+            Region::Element(e) => Some(e),
+            _ => None,
         }
     }
 
     pub fn element_mut(&mut self) -> Option<&mut Element> {
         match self {
             Region::Element(e) => Some(e),
-            // _ => None,
+            _ => None,
+        }
+    }
+
+    pub fn subdivision(&self) -> Option<&Regions> {
+        match self {
+            Region::Subdivision(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    /// Number of elements in the region.
+    pub fn count(&self) -> usize {
+        match self {
+            Region::Element(_) => 1,
+            Region::Subdivision(s) => s.iter().map(|e| e.count()).sum(),
+        }
+    }
+
+    /// Splits an element into four quadrants holding copies of it.
+    pub fn subdivide(&mut self) {
+        if let Region::Element(e) = self {
+            *self = Region::Subdivision(vec![Region::Element(e.clone()); 4]);
         }
     }
 
-    // pub fn subdivision(&self) -> Option<Regions> {
-    //     match self {
-    //         Region::Subdivision(s) => Some(s),
-    //         _ => None,
-    //     }
-    // }
+    /// Index of the quadrant holding `(x, y)`, and the coordinates inside of it.
+    fn quadrant(x: u32, y: u32, half: u32) -> (usize, u32, u32) {
+        let (right, bottom) = (x >= half, y >= half);
+        (
+            right as usize + 2 * bottom as usize,
+            x - half * right as u32,
+            y - half * bottom as u32,
+        )
+    }
 
-    // pub fn count(&self) -> usize {
-    //     match self {
-    //         Region::Element(_) => 1,
-    //         // Region::Subdivision(s) => s.map(|e| e.count()).sum(),
-    //     }
-    // }
+    /// Element covering `(x, y)` of a region of side `size`, with the side of its own region.
+    pub fn leaf(&self, x: u32, y: u32, size: u32) -> (&Region, u32) {
+        match self {
+            Region::Element(_) => (self, size),
+            Region::Subdivision(s) => {
+                let half = size / 2;
+                let (i, x, y) = Region::quadrant(x, y, half);
+                s[i].leaf(x, y, half)
+            }
+        }
+    }
+    pub fn leaf_mut(&mut self, x: u32, y: u32, size: u32) -> (&mut Region, u32) {
+        match self {
+            Region::Element(_) => (self, size),
+            Region::Subdivision(s) => {
+                let half = size / 2;
+                let (i, x, y) = Region::quadrant(x, y, half);
+                s[i].leaf_mut(x, y, half)
+            }
+        }
+    }
+
+    /// Subdivides, down to single cells, while `needs_detail(origin, size)` holds.
+    pub fn refine(
+        &mut self,
+        origin: (u32, u32),
+        size: u32,
+        needs_detail: &impl Fn((u32, u32), u32) -> bool,
+    ) {
+        if size <= 1 || !needs_detail(origin, size) {
+            return;
+        }
+        self.subdivide();
+        let half = size / 2;
+        if let Region::Subdivision(s) = self {
+            for (i, quadrant) in s.iter_mut().enumerate() {
+                let origin = (
+                    origin.0 + half * (i as u32 % 2),
+                    origin.1 + half * (i as u32 / 2),
+                );
+                quadrant.refine(origin, half, needs_detail);
+            }
+        }
+    }
+
+    /// Calls `f` with the origin, side and content of every element.
+    pub fn for_each_element(
+        &self,
+        origin: (u32, u32),
+        size: u32,
+        f: &mut impl FnMut((u32, u32), u32, &Element),
+    ) {
+        match self {
+            Region::Element(e) => f(origin, size, e),
+            Region::Subdivision(s) => {
+                let half = size / 2;
+                for (i, quadrant) in s.iter().enumerate() {
+                    let origin = (
+                        origin.0 + half * (i as u32 % 2),
+                        origin.1 + half * (i as u32 / 2),
+                    );
+                    quadrant.for_each_element(origin, half, f);
+                }
+            }
+        }
+    }
+    pub fn for_each_element_mut(
+        &mut self,
+        origin: (u32, u32),
+        size: u32,
+        f: &mut impl FnMut((u32, u32), u32, &mut Element),
+    ) {
+        match self {
+            Region::Element(e) => f(origin, size, e),
+            Region::Subdivision(s) => {
+                let half = size / 2;
+                for (i, quadrant) in s.iter_mut().enumerate() {
+                    let origin = (
+                        origin.0 + half * (i as u32 % 2),
+                        origin.1 + half * (i as u32 / 2),
+                    );
+                    quadrant.for_each_element_mut(origin, half, f);
+                }
+            }
+        }
+    }
 }
 
 type Regions = Vec<Region>;
@@ -74,6 +198,12 @@ pub struct Particle {
     pub position: Point,
     pub speed: Point,
     pub value: f64,
+    /// Index of the channel the particle carries the field of.
+    pub channel: usize,
+    /// Steps lived so far.
+    pub age: u32,
+    /// Index of the body which emitted the particle, if any.
+    pub body: Option<usize>,
 }
 
 impl Particle {
@@ -83,3 +213,21 @@ impl Particle {
         self.speed = speed;
     }
 }
+
+/// A point source free to move, accelerated by the field of its channel.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Body {
+    pub position: Point,
+    /// Displacement per step.
+    pub velocity: Point,
+    /// Source value emitted, in the body's channel.
+    pub value: f64,
+    pub channel: usize,
+}
+
+impl Body {
+    /// Bodies that left the universe stay where they left it, and are ignored.
+    pub fn is_inside(&self, universe: &Universe) -> bool {
+        self.position.is_inside(universe)
+    }
+}
@@ -75,9 +75,65 @@ impl Portal {
         self.relative_position(point).y
     }
 
-    pub(crate) fn into_complex_polar(self) -> ComplexPolar {
-        let delta = self.point_b - self.point_a;
-        delta.into()
+    /// Where the straight motion from `point` by `movement` crosses the portal, as the
+    /// fraction of `movement` done and the relative position along the portal.
+    ///
+    /// https://en.wikipedia.org/wiki/Line%E2%80%93line_intersection#Given_two_points_on_each_line
+    pub fn crossing(&self, point: Point, movement: Point) -> Option<(f64, f64)> {
+        let before = self.relative_position(point);
+        let after = self.relative_position(point + movement);
+        if before.y * after.y >= 0.0 {
+            return None;
+        }
+        /*
+        (x1, y1) := (0, 0)
+        (x2, y2) := (1, 0)
+        (x3, y3) := (Bx, By)
+        (x4, y4) := (Ax, Ay)
+        Py = [
+            (x1 y2 - y1 x2) (y3 - y4) - (y1 - y2) (x3 y4 - y3 x4)
+        ] / [
+            (x1 - x2) (y3 - y4) - (y1 - y2) (x3 - x4)
+        ] = 0
+        Px = [
+            (x1 y2 - y1 x2) (x3 - x4) - (x1 - x2) (x3 y4 - y3 x4)
+        ] / [
+            (x1 - x2) (y3 - y4) - (y1 - y2)(x3 - x4)
+        ]
+        = [ x3 y4 - y3 x4 ] / [ y4 - y3 ]
+        = [ Bx Ay - By Ax ] / [ Ay - By ]
+        */
+        let x = (before.x * after.y - before.y * after.x) / (after.y - before.y);
+        // We want x in [0; 1]
+        if !(0.0..1.0).contains(&x) {
+            return None;
+        }
+        Some((before.y / (before.y - after.y), x))
+    }
+
+    /// Carries a motion crossing this portal at the relative position `x` over to `exit`.
+    ///
+    /// Returns the position on `exit`, the speed, and the movement still to be done
+    /// to reach what `destination` becomes on the other side.
+    pub fn teleport(
+        &self,
+        exit: &Portal,
+        x: f64,
+        speed: Point,
+        destination: Point,
+    ) -> (Point, Point, Point) {
+        //* {space = entry portal}
+        let crossing_point = Point::by_x(x);
+        let yet_to_move = self.relative_position(destination) - crossing_point;
+
+        //* {space = real space}
+        let exit_delta = exit.point_b - exit.point_a;
+        let projection_entry_to_exit = exit_delta / (self.point_b - self.point_a);
+        (
+            exit.reverted_relative_position(crossing_point),
+            speed * projection_entry_to_exit,
+            yet_to_move * exit_delta,
+        )
     }
 }
 
@@ -107,86 +163,33 @@ impl PortalSet {
         [self.a.signed_distance(point), self.b.signed_distance(point)]
     }
 
-    /// https://en.wikipedia.org/wiki/Line%E2%80%93line_intersection#Given_two_points_on_each_line
+    /// Teleports the motion from `point` by `movement` through the first portal of the set
+    /// it crosses, returning the new position, speed and movement left, in real space.
     pub fn cross(
         &self,
         point: Point,
         speed: Point,
         movement: Point,
     ) -> Option<(Point, Point, Point)> {
-        /*
-        (x1, y1) := (0, 0)
-        (x2, y2) := (1, 0)
-        (x3, y3) := (Bx, By)
-        (x4, y4) := (Ax, Ay)
-        Py = [
-            (x1 y2 - y1 x2) (y3 - y4) - (y1 - y2) (x3 y4 - y3 x4)
-        ] / [
-            (x1 - x2) (y3 - y4) - (y1 - y2) (x3 - x4)
-        ] = 0
-        Px = [
-            (x1 y2 - y1 x2) (x3 - x4) - (x1 - x2) (x3 y4 - y3 x4)
-        ] / [
-            (x1 - x2) (y3 - y4) - (y1 - y2)(x3 - x4)
-        ]
-        = [ x3 y4 - y3 x4 ] / [ y4 - y3 ]
-        = [ Bx Ay - By Ax ] / [ Ay - By ]
-        */
-
-        let (being_crossed, exiting): (Portal, Portal) = {
-            let [before_a, before_b] = self.relative_positions(point);
-            let [after_a, after_b] = self.relative_positions(point + movement);
-            let cmp_a = self.a.into_complex_polar();
-            let cmp_b = self.b.into_complex_polar();
-            /*
-             * distance_A = if is crossing A { before_a.y * cmp_a.radius } else { None }
-             * distance_B = if is crossing B { before_b.y * cmp_b.radius } else { None }
-             * if distance_A.abs() > distance_B.abs() { (a, b) } else { (b, a) }
-             */
-            let distance_a = if before_a.y * after_a.y < 0.0 {
-                Some(before_a.y * cmp_a.radius)
-            } else {
-                None
-            };
-            let distance_b = if before_b.y * after_b.y < 0.0 {
-                Some(before_b.y * cmp_b.radius)
-            } else {
-                None
-            };
-            match (distance_a, distance_b) {
-                (None, None) => return None,
-                (Some(_), None) => (self.a, self.b),
-                (None, Some(_)) => (self.b, self.a),
-                (Some(distance_a), Some(distance_b)) => {
-                    if distance_a.abs() < distance_b.abs() {
-                        (self.a, self.b)
-                    } else {
-                        (self.b, self.a)
-                    }
+        let crossing_a = self.a.crossing(point, movement);
+        let crossing_b = self.b.crossing(point, movement);
+        let (being_crossed, exiting, x) = match (crossing_a, crossing_b) {
+            (None, None) => return None,
+            (Some((_, x)), None) => (self.a, self.b, x),
+            (None, Some((_, x))) => (self.b, self.a, x),
+            (Some((ta, xa)), Some((tb, xb))) => {
+                if ta < tb {
+                    (self.a, self.b, xa)
+                } else {
+                    (self.b, self.a, xb)
                 }
             }
         };
+        Some(being_crossed.teleport(&exiting, x, speed, point + movement))
+    }
 
-        //* {space = entry portal}
-        let before = being_crossed.relative_position(point);
-        let after = being_crossed.relative_position(point + movement);
-        let x = (before.x * after.y - before.y * after.x) / (after.y - before.y);
-        // We want x in [0; 1]
-        if !(0.0..1.0).contains(&x) {
-            // Not being crossed.
-            return None;
-        }
-        let crossing_point = Point::by_x(x);
-        let yet_to_move = after - crossing_point;
-
-        //* {space = real space}
-        let reprojected = exiting.reverted_relative_position(crossing_point);
-        let projection_entry_to_exit =
-            (exiting.point_b - exiting.point_a) / (being_crossed.point_b - being_crossed.point_a);
-        let speed = speed * projection_entry_to_exit;
-        // {space = entry portal}
-        // remaining := after - crossing point
-        let yet_to_move = exiting.reverted_relative_position(yet_to_move);
-        Some((reprojected, speed, yet_to_move))
+    /// Both directions of travel, as `(entry, exit)`.
+    pub fn pairs(&self) -> [(Portal, Portal); 2] {
+        [(self.a, self.b), (self.b, self.a)]
     }
 }
@@ -1,31 +1,168 @@
-use super::{Element, Point, Region, Regions, portal::PortalSet};
+use super::{Channel, Element, Point, Region, Regions, portal::PortalSet};
 
 use std::ops::{Index, IndexMut};
 
-use colorgrad::Gradient;
-use image::{DynamicImage, ImageBuffer, Rgb};
-use rayon::prelude::*;
-
 #[derive(Debug, Clone, Default)]
 pub struct Universe {
     pub width: u32,
     pub height: u32,
     portals: Vec<PortalSet>,
+    channels: Vec<Channel>,
+    /// Side of the square regions tiling the universe, row by row, in `data`.
+    block_size: u32,
     data: Regions,
 }
 
 impl Universe {
-    pub fn new(width: u32, height: u32) -> Universe {
-        let data = vec![Default::default(); (width * height) as usize];
+    pub fn new(width: u32, height: u32, channels: Vec<Channel>) -> Universe {
+        Universe::adaptive(width, height, 1, channels)
+    }
+
+    /// A universe tiled by square regions of side `block_size`, a power of two, holding
+    /// a single element each until [`Universe::refine`] subdivides them.
+    pub fn adaptive(width: u32, height: u32, block_size: u32, channels: Vec<Channel>) -> Universe {
+        assert!(
+            block_size.is_power_of_two(),
+            "block size must be a power of two, got {block_size}"
+        );
+        let blocks = Universe::block_count(width, height, block_size) as usize;
+        let blocks = vec![Region::Element(Element::new(channels.len())); blocks];
+        Universe::from_blocks(width, height, block_size, channels, blocks)
+    }
+
+    /// A universe tiled by `blocks`, row by row, as by [`Universe::adaptive`].
+    ///
+    /// # Panics
+    ///
+    /// If there isn't one block per tile.
+    pub(crate) fn from_blocks(
+        width: u32,
+        height: u32,
+        block_size: u32,
+        channels: Vec<Channel>,
+        blocks: Regions,
+    ) -> Universe {
+        assert_eq!(
+            blocks.len() as u64,
+            Universe::block_count(width, height, block_size),
+            "blocks don't tile the universe"
+        );
         Universe {
             width,
             height,
-            data,
+            block_size,
+            data: blocks,
+            channels,
             ..Default::default()
         }
     }
 
-    pub fn get_from_point(&self, point: Point) -> Option<Element> {
+    /// Blocks of side `block_size` tiling a `width`×`height` universe.
+    pub(crate) fn block_count(width: u32, height: u32, block_size: u32) -> u64 {
+        width.div_ceil(block_size) as u64 * height.div_ceil(block_size) as u64
+    }
+
+    pub fn channels(&self) -> &[Channel] {
+        &self.channels
+    }
+    /// Channels can be tuned, but not added or removed: elements hold one property each.
+    pub fn channels_mut(&mut self) -> &mut [Channel] {
+        &mut self.channels
+    }
+    pub fn channel_index(&self, name: &str) -> Option<usize> {
+        self.channels.iter().position(|c| c.name == name)
+    }
+
+    pub fn block_size(&self) -> u32 {
+        self.block_size
+    }
+
+    /// Top-level regions, row by row.
+    pub(crate) fn blocks(&self) -> &[Region] {
+        &self.data
+    }
+
+    fn block_origin(&self, index: usize) -> (u32, u32) {
+        let per_row = self.width.div_ceil(self.block_size) as usize;
+        (
+            (index % per_row) as u32 * self.block_size,
+            (index / per_row) as u32 * self.block_size,
+        )
+    }
+
+    fn block_index(&self, x: u32, y: u32) -> usize {
+        let shift = self.block_size.trailing_zeros();
+        let per_row = self.width.div_ceil(self.block_size) as usize;
+        (x >> shift) as usize + (y >> shift) as usize * per_row
+    }
+
+    /// Number of elements, as opposed to the `width × height` cells they cover.
+    pub fn element_count(&self) -> usize {
+        self.data.iter().map(Region::count).sum()
+    }
+
+    /// Subdivides regions, down to single cells, while `needs_detail(center, size)` holds.
+    ///
+    /// Quadrants lying beyond the edge of the universe, in blocks sticking out of it, are
+    /// never subdivided.
+    pub fn refine(&mut self, needs_detail: impl Fn(Point, u32) -> bool) {
+        let (width, height) = (self.width, self.height);
+        let needs_detail = |(x, y): (u32, u32), size: u32| {
+            if x >= width || y >= height {
+                return false;
+            }
+            let half = size as f64 / 2.0;
+            needs_detail(
+                Point {
+                    x: x as f64 + half,
+                    y: y as f64 + half,
+                },
+                size,
+            )
+        };
+        for i in 0..self.data.len() {
+            let origin = self.block_origin(i);
+            self.data[i].refine(origin, self.block_size, &needs_detail);
+        }
+    }
+
+    /// Calls `f` with the origin, side and content of every element.
+    pub fn for_each_element(&self, mut f: impl FnMut((u32, u32), u32, &Element)) {
+        for (i, block) in self.data.iter().enumerate() {
+            block.for_each_element(self.block_origin(i), self.block_size, &mut f);
+        }
+    }
+    pub fn for_each_element_mut(&mut self, mut f: impl FnMut((u32, u32), u32, &mut Element)) {
+        for i in 0..self.data.len() {
+            let origin = self.block_origin(i);
+            self.data[i].for_each_element_mut(origin, self.block_size, &mut f);
+        }
+    }
+
+    /// Resets the field of every channel, keeping the sources.
+    pub fn clear_fields(&mut self) {
+        self.for_each_element_mut(|_, _, element| {
+            for property in &mut element.properties {
+                property.field = Default::default();
+            }
+        });
+    }
+
+    /// Whether any element holds a source of that channel.
+    pub fn has_source(&self, channel: usize) -> bool {
+        let mut found = false;
+        self.for_each_element(|_, _, element| {
+            found |= element.property(channel).value != 0.0;
+        });
+        found
+    }
+
+    /// Area of the universe covered by the region at `origin` of side `size`.
+    pub fn area(&self, origin: (u32, u32), size: u32) -> f64 {
+        clipped_area((self.width, self.height), origin, size)
+    }
+
+    pub fn get_from_point(&self, point: Point) -> Option<&Element> {
         if !point.is_inside(self) {
             return None;
         }
@@ -37,32 +174,81 @@ impl Universe {
         }
         self[(point.x as u32, point.y as u32)].element_mut()
     }
+    /// Like [`Universe::get_from_point_mut`], along with the area covered by the element.
+    pub fn get_cell_from_point_mut(&mut self, point: Point) -> Option<(&mut Element, f64)> {
+        if !point.is_inside(self) {
+            return None;
+        }
+        let (x, y) = (point.x as u32, point.y as u32);
+        let size = self.block_size;
+        let index = self.block_index(x, y);
+        let (width, height) = (self.width, self.height);
+        let (region, side) = self.data[index].leaf_mut(x % size, y % size, size);
+        // Regions are aligned on multiples of their side.
+        let area = clipped_area((width, height), (x - x % side, y - y % side), side);
+        region.element_mut().map(|e| (e, area))
+    }
+
+    /// Origin of the region of the element covering `point`, which identifies it.
+    pub fn cell_origin(&self, point: Point) -> Option<(u32, u32)> {
+        if !point.is_inside(self) {
+            return None;
+        }
+        let (x, y) = (point.x as u32, point.y as u32);
+        let size = self.block_size;
+        let (_, side) = self.data[self.block_index(x, y)].leaf(x % size, y % size, size);
+        Some((x - x % side, y - y % side))
+    }
 
     pub fn add_portal_set(&mut self, portal: PortalSet) {
         self.portals.push(portal);
     }
 
-    /// TODO: test multiple portal sets
+    pub fn portals(&self) -> &[PortalSet] {
+        &self.portals
+    }
+
+    /// Moves `point` by `speed`, teleporting through every portal met on the way, from
+    /// any portal set, in the order they're crossed.
+    ///
+    /// Returns the final position and the speed, transformed by the portals crossed.
     pub fn move_in_universe(&self, point: Point, speed: Point) -> (Point, Point) {
+        /// Bound on teleports per move, against portals facing each other at distance 0.
+        const MAX_CROSSINGS: usize = 64;
         let mut point = point;
         let mut speed = speed;
         let mut yet_to_move: Point = speed;
-        loop {
-            let cross = self.portals[0].cross(point, speed, yet_to_move);
-            if cross.is_none() {
-                return (point + speed, speed);
-            }
-            (point, speed, yet_to_move) = cross.unwrap();
-            if yet_to_move.magnitude_2() <= 1.0 {
-                point += yet_to_move;
-                break;
-            }
+        // The exit portal lies on the start of the remaining path, it can't be crossed again.
+        let mut exited = None;
+        for _ in 0..MAX_CROSSINGS {
+            let first_crossing = self
+                .portals
+                .iter()
+                .enumerate()
+                .flat_map(|(i, portalset)| {
+                    portalset
+                        .pairs()
+                        .into_iter()
+                        .enumerate()
+                        .map(move |(side, pair)| ((i, side), pair))
+                })
+                .filter(|(id, _)| Some(*id) != exited)
+                .filter_map(|(id, (entry, exit))| {
+                    let (t, x) = entry.crossing(point, yet_to_move)?;
+                    Some((t, x, id, entry, exit))
+                })
+                .min_by(|a, b| a.0.total_cmp(&b.0));
+            let Some((_, x, (i, side), entry, exit)) = first_crossing else {
+                return (point + yet_to_move, speed);
+            };
+            (point, speed, yet_to_move) = entry.teleport(&exit, x, speed, point + yet_to_move);
+            exited = Some((i, 1 - side));
         }
         (point, speed)
     }
 
     pub fn section(&self, x: u32, y: u32, width: u32, height: u32) -> Universe {
-        let mut universe = Universe::new(width, height);
+        let mut universe = Universe::new(width, height, self.channels.clone());
         for yi in 0..height {
             for xi in 0..width {
                 universe[(xi, yi)] = self[(x + xi, y + yi)].clone();
@@ -72,201 +258,98 @@ impl Universe {
     }
 }
 
+fn clipped_area((width, height): (u32, u32), (x, y): (u32, u32), size: u32) -> f64 {
+    let width = (x + size).min(width).saturating_sub(x);
+    let height = (y + size).min(height).saturating_sub(y);
+    width as f64 * height as f64
+}
+
+/// Indexing by cell gives the element covering it, whatever the size of its region.
 impl Index<(u32, u32)> for Universe {
     type Output = Region;
     fn index(&self, (x, y): (u32, u32)) -> &Region {
-        &self.data[(x + y * self.width) as usize]
+        let size = self.block_size;
+        self.data[self.block_index(x, y)]
+            .leaf(x % size, y % size, size)
+            .0
     }
 }
 impl IndexMut<(u32, u32)> for Universe {
     fn index_mut(&mut self, (x, y): (u32, u32)) -> &mut Region {
-        &mut self.data[(x + y * self.width) as usize]
+        let size = self.block_size;
+        let index = self.block_index(x, y);
+        self.data[index].leaf_mut(x % size, y % size, size).0
     }
 }
 
-impl Universe {
-    fn normalize(&self) -> Universe {
-        let mut mass_min = f64::MAX;
-        let mut mass_max = f64::MIN;
-        let mut mass_field_mag_max = f64::MIN;
-        for y in 0..self.height {
-            for x in 0..self.width {
-                let element = self[(x, y)].element().unwrap();
-                let mass = element.mass.value;
-                mass_min = mass_min.min(mass);
-                mass_max = mass_max.max(mass);
-                let mag = element.mass.field.magnitude();
-                mass_field_mag_max = mass_field_mag_max.max(mag);
-            }
-        }
-        let mut new = self.clone();
-        for y in 0..new.height {
-            for x in 0..new.width {
-                let mut element = new[(x, y)].element().unwrap();
-                let mass = element.mass.value;
-                element.mass.value = (mass - mass_min) / (mass_max - mass_min);
-                element.mass.field /= mass_field_mag_max;
-                new[(x, y)] = Region::Element(element);
-            }
-        }
-        new
-    }
-    pub fn to_image(&self) -> DynamicImage {
-        let universe = self.normalize();
-        //* Create image
-        let mut img = ImageBuffer::new(universe.width, universe.height);
-        //* Draw portals
-        let minus = Point { x: -1.0, y: 0.0 };
-        let plus = Point { x: 1.0, y: 0.0 };
-        const PORTAL_COLOUR: Rgb<u8> = Rgb([192, 32, 32]);
-        for portalset in self.portals.iter() {
-            let PortalSet { a, b } = portalset;
-            draw_line(&mut img, a.point_a, a.point_b, PORTAL_COLOUR);
-            draw_line(
-                &mut img,
-                a.point_a + minus,
-                a.point_b + minus,
-                PORTAL_COLOUR,
-            );
-            draw_line(&mut img, a.point_a + plus, a.point_b + plus, PORTAL_COLOUR);
-            draw_line(&mut img, b.point_a, b.point_b, PORTAL_COLOUR);
-            draw_line(
-                &mut img,
-                b.point_a + minus,
-                b.point_b + minus,
-                PORTAL_COLOUR,
-            );
-            draw_line(&mut img, b.point_a + plus, b.point_b + plus, PORTAL_COLOUR);
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::Portal;
+
+    fn p(x: f64, y: f64) -> Point {
+        Point { x, y }
+    }
+
+    /// A horizontal portal at `y`, from `x = 0` to `x = 100`.
+    fn horizontal(y: f64) -> Portal {
+        Portal::new(p(0.0, y), p(100.0, y))
+    }
+
+    fn universe(portals: &[PortalSet]) -> Universe {
+        let mut universe = Universe::new(100, 200, vec![Channel::mass()]);
+        for &portalset in portals {
+            universe.add_portal_set(portalset);
         }
-        //* Draw field(s)
-        let gradient = colorgrad::preset::viridis();
-        img.par_enumerate_pixels_mut().for_each(|(x, y, pixel)| {
-            let element = universe[(x, y)].element().unwrap();
-            let field = element.mass.field;
-            let mag = field.magnitude();
-
-            let [r, g, b, ..] = gradient.at(mag as f32).to_rgba8();
-
-            if *pixel == PORTAL_COLOUR {
-                *pixel = Rgb([
-                    ((r as f64 + pixel.0[0] as f64) * 0.5) as u8,
-                    ((g as f64 + pixel.0[1] as f64) * 0.5) as u8,
-                    ((b as f64 + pixel.0[2] as f64) * 0.5) as u8,
-                ]);
-            } else {
-                *pixel = Rgb([r, g, b]);
-            }
-        });
-        DynamicImage::ImageRgb8(img)
+        universe
     }
-}
 
-use core::ops::{Deref, DerefMut};
-
-/// https://en.wikipedia.org/wiki/Line_drawing_algorithm
-/// https://en.wikipedia.org/wiki/Bresenham%27s_line_algorithm
-/// https://rosettacode.org/wiki/Bitmap/Bresenham%27s_line_algorithm
-fn draw_line<Pixel: image::Pixel, Container: Deref<Target = [Pixel::Subpixel]> + DerefMut>(
-    img: &mut ImageBuffer<Pixel, Container>,
-    start: Point,
-    end: Point,
-    color: Pixel,
-) {
-    // println!("Drawing line from {} to {}", start, end);
-    let Point { x: x0, y: y0 } = start;
-    let Point { x: x1, y: y1 } = end;
-    let dx = x1 - x0;
-    let dy = y1 - y0;
-
-    if dy < dx {
-        draw_line_low(
-            img,
-            if x0 < x1 { (start, end) } else { (end, start) },
-            color,
-        );
-    } else {
-        draw_line_high(
-            img,
-            if y0 < y1 { (start, end) } else { (end, start) },
-            color,
+    fn assert_close(actual: Point, expected: Point) {
+        assert!(
+            (actual - expected).magnitude() < 1e-9,
+            "expected {expected}, got {actual}"
         );
     }
-}
 
-#[inline]
-fn draw_line_low<Pixel: image::Pixel, Container: Deref<Target = [Pixel::Subpixel]> + DerefMut>(
-    img: &mut ImageBuffer<Pixel, Container>,
-    path: (Point, Point),
-    color: Pixel,
-) {
-    let Point { x: x0, y: y0 } = path.0;
-    let Point { x: x1, y: y1 } = path.1;
-    let dx = x1 - x0;
-    let dy = y1 - y0;
-    let mut d = 2.0 * dy - dx;
-    let mut y = y0.round() as u32;
-
-    if dy > 0.0 {
-        for x in x0.round() as u32..=x1.round() as u32 {
-            if let Some(p) = img.get_pixel_mut_checked(x, y) {
-                *p = color;
-            }
-            if d > 0.0 {
-                y += 1;
-                d += 2.0 * (dy - dx);
-            }
-            d += 2.0 * dy;
-        }
-    } else {
-        let dy = dy.abs();
-        for x in x0.round() as u32..=x1.round() as u32 {
-            if let Some(p) = img.get_pixel_mut_checked(x, y) {
-                *p = color;
-            }
-            if d > 0.0 {
-                y -= 1;
-                d += 2.0 * (dy - dx);
-            }
-            d += 2.0 * dy;
-        }
+    #[test]
+    fn moves_straight_without_portals() {
+        let (position, speed) = universe(&[]).move_in_universe(p(10.0, 15.0), p(3.0, 4.0));
+        assert_eq!((position, speed), (p(13.0, 19.0), p(3.0, 4.0)));
     }
-}
 
-#[inline]
-fn draw_line_high<Pixel: image::Pixel, Container: Deref<Target = [Pixel::Subpixel]> + DerefMut>(
-    img: &mut ImageBuffer<Pixel, Container>,
-    path: (Point, Point),
-    color: Pixel,
-) {
-    let Point { x: x0, y: y0 } = path.0;
-    let Point { x: x1, y: y1 } = path.1;
-    let dx = x1 - x0;
-    let dy = y1 - y0;
-    let mut d = 2.0 * dx - dy;
-    let mut x = x0.round() as u32;
-
-    if dx > 0.0 {
-        for y in y0.round() as u32..=y1.round() as u32 {
-            if let Some(p) = img.get_pixel_mut_checked(x, y) {
-                *p = color;
-            }
-            if d > 0.0 {
-                x += 1;
-                d += 2.0 * (dx - dy);
-            }
-            d += 2.0 * dx;
-        }
-    } else {
-        let dx = dx.abs();
-        for y in y0.round() as u32..=y1.round() as u32 {
-            if let Some(p) = img.get_pixel_mut_checked(x, y) {
-                *p = color;
-            }
-            if d > 0.0 {
-                x -= 1;
-                d += 2.0 * (dx - dy);
-            }
-            d += 2.0 * dx;
-        }
+    #[test]
+    fn chains_portals_of_different_sets_in_one_move() {
+        let universe = universe(&[
+            PortalSet::new(horizontal(20.0), horizontal(60.0)),
+            PortalSet::new(horizontal(70.0), horizontal(30.0)),
+        ]);
+        // 5 to the first portal, out at 60, 10 to the second, out at 30, then 5 more.
+        let (position, speed) = universe.move_in_universe(p(50.0, 15.0), p(0.0, 20.0));
+        assert_close(position, p(50.0, 35.0));
+        assert_close(speed, p(0.0, 20.0));
+    }
+
+    #[test]
+    fn crosses_the_earliest_portal_first() {
+        let universe = universe(&[
+            PortalSet::new(horizontal(28.0), horizontal(100.0)),
+            PortalSet::new(horizontal(25.0), horizontal(110.0)),
+            PortalSet::new(horizontal(27.0), horizontal(120.0)),
+        ]);
+        let (position, _) = universe.move_in_universe(p(50.0, 20.0), p(0.0, 10.0));
+        assert_close(position, p(50.0, 115.0));
+        // Backwards, 28 comes first: out at 100, 8 more.
+        let (position, _) = universe.move_in_universe(p(50.0, 30.0), p(0.0, -10.0));
+        assert_close(position, p(50.0, 92.0));
+    }
+
+    #[test]
+    fn rotated_exits_rotate_the_speed() {
+        let vertical = Portal::new(p(60.0, 0.0), p(60.0, 100.0));
+        let universe = universe(&[PortalSet::new(horizontal(20.0), vertical)]);
+        // Crossing the middle of the entry, a quarter turn to the exit.
+        let (position, speed) = universe.move_in_universe(p(50.0, 15.0), p(0.0, 10.0));
+        assert_close(position, p(55.0, 50.0));
+        assert_close(speed, p(-10.0, 0.0));
     }
 }
@@ -0,0 +1,350 @@
+//! Joining rendered frames into a video.
+
+use image::{
+    Delay, Frame, RgbImage,
+    codecs::gif::{GifEncoder, Repeat},
+};
+
+use std::{
+    fs::{self, File},
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+    process::{Child, Command, Stdio},
+    str::FromStr,
+};
+
+/// How the frames are joined.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum VideoFormat {
+    /// An MP4 video encoded by ffmpeg from the frames once rendered.
+    #[default]
+    Mp4,
+    /// An animated GIF, encoded without ffmpeg.
+    Gif,
+    /// An animated PNG, encoded without ffmpeg.
+    Apng,
+    /// An MP4 video, the frames streamed to ffmpeg while rendered instead of being saved.
+    Pipe,
+}
+
+impl VideoFormat {
+    pub fn extension(self) -> &'static str {
+        match self {
+            VideoFormat::Mp4 | VideoFormat::Pipe => "mp4",
+            VideoFormat::Gif => "gif",
+            VideoFormat::Apng => "apng",
+        }
+    }
+
+    /// Joins the frames in `folder` into `output`; [`VideoFormat::Pipe`] has nothing to
+    /// join once they're rendered.
+    pub fn join(self, folder: &Path, frame_rate: u32, output: &Path) -> io::Result<()> {
+        check_frame_rate(frame_rate)?;
+        match self {
+            VideoFormat::Mp4 => join_frames(folder, frame_rate, output),
+            VideoFormat::Gif => encode_gif(folder, frame_rate, output),
+            VideoFormat::Apng => encode_apng(folder, frame_rate, output),
+            VideoFormat::Pipe => Ok(()),
+        }
+    }
+}
+
+impl FromStr for VideoFormat {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "mp4" => Ok(VideoFormat::Mp4),
+            "gif" => Ok(VideoFormat::Gif),
+            "apng" => Ok(VideoFormat::Apng),
+            "pipe" => Ok(VideoFormat::Pipe),
+            _ => Err(format!(
+                "unknown video format `{s}`, expected `mp4`, `gif`, `apng` or `pipe`"
+            )),
+        }
+    }
+}
+
+/// Fails with [`io::ErrorKind::InvalidInput`] on a frame rate of 0, which no frame delay
+/// can express.
+fn check_frame_rate(frame_rate: u32) -> io::Result<()> {
+    if frame_rate == 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "frame rate must be positive",
+        ));
+    }
+    Ok(())
+}
+
+/// Path of the `index`th frame in `folder`.
+pub fn frame_path(folder: &Path, index: u32) -> PathBuf {
+    folder.join(format!("{index:04}.png"))
+}
+
+/// Creates `folder`, or removes the images left in it by a previous run.
+pub fn clear_frames(folder: &Path) -> io::Result<()> {
+    if !fs::exists(folder)? {
+        return fs::create_dir_all(folder);
+    }
+    for f in fs::read_dir(folder)? {
+        let path = f?.path();
+        if path.extension().is_some_and(|ext| ext == "png") {
+            fs::remove_file(path)?;
+        }
+    }
+    Ok(())
+}
+
+/// Paths of the frames saved in `folder`, in order, up to the first missing.
+pub fn frame_paths(folder: &Path) -> impl Iterator<Item = PathBuf> {
+    (0..)
+        .map(|i| frame_path(folder, i))
+        .take_while(|path| path.exists())
+}
+
+/// Arguments making ffmpeg encode the frames `folder/0000.png`, `folder/0001.png`, …
+/// into `output`.
+pub fn ffmpeg_arguments(folder: &Path, frame_rate: u32, output: &Path) -> Vec<String> {
+    [
+        "-y",
+        "-loglevel",
+        "error",
+        "-framerate",
+        &frame_rate.to_string(),
+        "-i",
+        &format!("{}/%04d.png", folder.display()),
+    ]
+    .into_iter()
+    .map(String::from)
+    .chain(encoding_arguments(output))
+    .collect()
+}
+
+/// Arguments encoding the input, of any size, as H.264 into `output`.
+fn encoding_arguments(output: &Path) -> Vec<String> {
+    [
+        "-c:v",
+        "libx264",
+        "-pix_fmt",
+        "yuv420p",
+        // Even sizes, for yuv420p.
+        "-vf",
+        "scale=trunc(iw/2)*2:trunc(ih/2)*2",
+        &output.display().to_string(),
+    ]
+    .map(String::from)
+    .into()
+}
+
+/// Runs ffmpeg with [`ffmpeg_arguments`], failing with its errors if it does.
+pub fn join_frames(folder: &Path, frame_rate: u32, output: &Path) -> io::Result<()> {
+    let result = Command::new("ffmpeg")
+        .args(ffmpeg_arguments(folder, frame_rate, output))
+        .output()?;
+    if !result.status.success() {
+        let errors = String::from_utf8_lossy(&result.stderr);
+        let message = match errors.trim() {
+            "" => format!("ffmpeg {}", result.status),
+            errors => format!("ffmpeg {}: {errors}", result.status),
+        };
+        return Err(io::Error::other(message));
+    }
+    Ok(())
+}
+
+/// Encodes the frames in `folder` as an endlessly looping GIF.
+pub fn encode_gif(folder: &Path, frame_rate: u32, output: &Path) -> io::Result<()> {
+    /// Of the colour quantization, from 1, the best, to 30, the fastest.
+    const SPEED: i32 = 10;
+    check_frame_rate(frame_rate)?;
+    let paths: Vec<PathBuf> = frame_paths(folder).collect();
+    if paths.is_empty() {
+        return Err(io::Error::other("no frames to encode"));
+    }
+    let file = BufWriter::new(File::create(output)?);
+    let mut encoder = GifEncoder::new_with_speed(file, SPEED);
+    encoder
+        .set_repeat(Repeat::Infinite)
+        .map_err(io::Error::other)?;
+    let delay = Delay::from_numer_denom_ms(1000, frame_rate);
+    for path in paths {
+        let frame = image::open(path).map_err(io::Error::other)?.to_rgba8();
+        encoder
+            .encode_frame(Frame::from_parts(frame, 0, 0, delay))
+            .map_err(io::Error::other)?;
+    }
+    Ok(())
+}
+
+/// Encodes the frames in `folder` as an endlessly looping animated PNG.
+pub fn encode_apng(folder: &Path, frame_rate: u32, output: &Path) -> io::Result<()> {
+    check_frame_rate(frame_rate)?;
+    let paths: Vec<PathBuf> = frame_paths(folder).collect();
+    let Some(first) = paths.first() else {
+        return Err(io::Error::other("no frames to encode"));
+    };
+    let (width, height) = image::image_dimensions(first).map_err(io::Error::other)?;
+    let file = BufWriter::new(File::create(output)?);
+    let mut encoder = png::Encoder::new(file, width, height);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder
+        .set_animated(paths.len() as u32, 0)
+        .map_err(io::Error::other)?;
+    encoder
+        .set_frame_delay(1, frame_rate.min(u16::MAX as u32) as u16)
+        .map_err(io::Error::other)?;
+    let mut writer = encoder.write_header().map_err(io::Error::other)?;
+    for path in &paths {
+        let frame = image::open(path).map_err(io::Error::other)?.to_rgb8();
+        if frame.dimensions() != (width, height) {
+            return Err(io::Error::other(format!(
+                "frame `{}` isn't {width}×{height}",
+                path.display()
+            )));
+        }
+        writer.write_image_data(&frame).map_err(io::Error::other)?;
+    }
+    writer.finish().map_err(io::Error::other)
+}
+
+/// An ffmpeg process encoding the raw frames written to it into a video.
+pub struct FfmpegPipe {
+    child: Child,
+    size: (u32, u32),
+}
+
+impl FfmpegPipe {
+    pub fn spawn(width: u32, height: u32, frame_rate: u32, output: &Path) -> io::Result<Self> {
+        let child = Command::new("ffmpeg")
+            .args(pipe_arguments(width, height, frame_rate, output))
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .spawn()?;
+        Ok(FfmpegPipe {
+            child,
+            size: (width, height),
+        })
+    }
+
+    pub fn write_frame(&mut self, frame: &RgbImage) -> io::Result<()> {
+        if frame.dimensions() != self.size {
+            return Err(io::Error::other("frame of another size than the video's"));
+        }
+        let stdin = self.child.stdin.as_mut().expect("stdin is piped");
+        stdin.write_all(frame.as_raw())
+    }
+
+    /// Waits for ffmpeg to encode the last frames, failing if it does.
+    pub fn finish(mut self) -> io::Result<()> {
+        // Closing its input ends the video.
+        drop(self.child.stdin.take());
+        let status = self.child.wait()?;
+        if !status.success() {
+            return Err(io::Error::other(format!("ffmpeg {status}")));
+        }
+        Ok(())
+    }
+}
+
+/// Arguments making ffmpeg encode the raw RGB frames read from its input into `output`.
+pub fn pipe_arguments(width: u32, height: u32, frame_rate: u32, output: &Path) -> Vec<String> {
+    [
+        "-y",
+        "-loglevel",
+        "error",
+        "-f",
+        "rawvideo",
+        "-pix_fmt",
+        "rgb24",
+        "-video_size",
+        &format!("{width}x{height}"),
+        "-framerate",
+        &frame_rate.to_string(),
+        "-i",
+        "-",
+    ]
+    .into_iter()
+    .map(String::from)
+    .chain(encoding_arguments(output))
+    .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{AnimationDecoder, Rgb, codecs::gif::GifDecoder};
+    use std::{io::BufReader, time::Duration};
+
+    const SIZE: (u32, u32) = (6, 4);
+    const FRAMES: u8 = 3;
+    const FRAME_RATE: u32 = 10;
+
+    /// A fresh folder of `FRAMES` frames, each of a single colour.
+    fn frames(name: &str) -> PathBuf {
+        let folder = std::env::temp_dir().join(format!("{name}-{}", std::process::id()));
+        clear_frames(&folder).unwrap();
+        for i in 0..FRAMES {
+            let frame = RgbImage::from_pixel(SIZE.0, SIZE.1, Rgb([i * 100, 50, 200 - i * 100]));
+            frame.save(frame_path(&folder, i as u32)).unwrap();
+        }
+        folder
+    }
+
+    #[test]
+    fn gifs_hold_every_frame() {
+        let folder = frames("gif");
+        let output = folder.with_extension("gif");
+        encode_gif(&folder, FRAME_RATE, &output).unwrap();
+        let file = BufReader::new(File::open(&output).unwrap());
+        let frames = GifDecoder::new(file).unwrap().into_frames();
+        let frames = frames.collect_frames().unwrap();
+        fs::remove_dir_all(&folder).unwrap();
+        fs::remove_file(&output).unwrap();
+
+        assert_eq!(frames.len(), FRAMES as usize);
+        for frame in frames {
+            assert_eq!(frame.buffer().dimensions(), SIZE);
+            assert_eq!(Duration::from(frame.delay()), Duration::from_millis(100));
+        }
+    }
+
+    #[test]
+    fn animated_pngs_hold_every_frame() {
+        let folder = frames("apng");
+        let output = folder.with_extension("apng");
+        encode_apng(&folder, FRAME_RATE, &output).unwrap();
+        let file = BufReader::new(File::open(&output).unwrap());
+        let mut reader = png::Decoder::new(file).read_info().unwrap();
+        let mut buffer = vec![0; reader.output_buffer_size().unwrap()];
+        let count = reader.info().animation_control.unwrap().num_frames;
+        let mut delays = Vec::new();
+        for _ in 0..count {
+            let frame = reader.next_frame(&mut buffer).unwrap();
+            assert_eq!((frame.width, frame.height), SIZE);
+            let control = reader.info().frame_control.unwrap();
+            delays.push((control.delay_num, control.delay_den));
+        }
+        fs::remove_dir_all(&folder).unwrap();
+        fs::remove_file(&output).unwrap();
+
+        assert_eq!(count, FRAMES as u32);
+        assert_eq!(delays, vec![(1, FRAME_RATE as u16); FRAMES as usize]);
+    }
+
+    #[test]
+    fn encoders_reject_a_frame_rate_of_zero() {
+        let folder = frames("zero");
+        for format in [VideoFormat::Mp4, VideoFormat::Gif, VideoFormat::Apng] {
+            let output = folder.with_extension(format.extension());
+            let error = format.join(&folder, 0, &output).unwrap_err();
+            assert_eq!(error.kind(), io::ErrorKind::InvalidInput, "{format:?}");
+            assert!(!output.exists());
+        }
+        let error = encode_gif(&folder, 0, &folder.with_extension("gif")).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+        let error = encode_apng(&folder, 0, &folder.with_extension("apng")).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+        fs::remove_dir_all(&folder).unwrap();
+    }
+}
@@ -0,0 +1,419 @@
+//! Fields and portals as VTK files, to inspect them in ParaView.
+//!
+//! The fields are image data with one cell per cell of the universe: for every channel, a
+//! scalar array named after it with its value, and a vector array `<channel>_field` with its
+//! field, `z` being `0`. The portals are polydata, one line per portal, with the index of
+//! their set as cell data. Both use the universe's coordinates, `y` going down.
+
+use crate::types::{Point, Property, Universe};
+
+use std::{
+    fmt::{Display, Formatter, Result as FmtResult},
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::Path,
+    str::FromStr,
+};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum VtkFormat {
+    /// Legacy ASCII `.vtk` files.
+    Legacy,
+    /// XML `.vti` image data and `.vtp` polydata.
+    #[default]
+    Xml,
+}
+
+impl VtkFormat {
+    pub fn image_extension(self) -> &'static str {
+        match self {
+            VtkFormat::Legacy => "vtk",
+            VtkFormat::Xml => "vti",
+        }
+    }
+
+    pub fn polydata_extension(self) -> &'static str {
+        match self {
+            VtkFormat::Legacy => "vtk",
+            VtkFormat::Xml => "vtp",
+        }
+    }
+}
+
+impl FromStr for VtkFormat {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "legacy" => Ok(VtkFormat::Legacy),
+            "xml" => Ok(VtkFormat::Xml),
+            _ => Err(format!(
+                "unknown VTK format `{s}`, expected `legacy` or `xml`"
+            )),
+        }
+    }
+}
+
+impl Display for VtkFormat {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        f.write_str(match self {
+            VtkFormat::Legacy => "legacy",
+            VtkFormat::Xml => "xml",
+        })
+    }
+}
+
+pub fn save_image(
+    universe: &Universe,
+    path: impl AsRef<Path>,
+    format: VtkFormat,
+) -> io::Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    match format {
+        VtkFormat::Legacy => write_legacy_image(&mut writer, universe)?,
+        VtkFormat::Xml => write_xml_image(&mut writer, universe)?,
+    }
+    writer.flush()
+}
+
+pub fn save_portals(
+    universe: &Universe,
+    path: impl AsRef<Path>,
+    format: VtkFormat,
+) -> io::Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    match format {
+        VtkFormat::Legacy => write_legacy_portals(&mut writer, universe)?,
+        VtkFormat::Xml => write_xml_portals(&mut writer, universe)?,
+    }
+    writer.flush()
+}
+
+/// The property of `channel` in every cell, row by row.
+fn cells(universe: &Universe, channel: usize) -> impl Iterator<Item = &Property> {
+    (0..universe.height)
+        .flat_map(move |y| (0..universe.width).map(move |x| (x, y)))
+        .map(move |cell| universe[cell].element().unwrap().property(channel))
+}
+
+/// Ends of every portal, with the index of its set.
+fn portal_lines(universe: &Universe) -> Vec<(usize, Point, Point)> {
+    (universe.portals().iter().enumerate())
+        .flat_map(|(i, set)| [set.a, set.b].map(|portal| (i, portal.point_a, portal.point_b)))
+        .collect()
+}
+
+/// VTK names can't hold spaces in legacy files.
+fn array_name(name: &str) -> String {
+    name.replace(char::is_whitespace, "_")
+}
+
+pub fn write_legacy_image(writer: &mut impl Write, universe: &Universe) -> io::Result<()> {
+    let (width, height) = (universe.width, universe.height);
+    writeln!(writer, "# vtk DataFile Version 3.0")?;
+    writeln!(writer, "simulador_de_fluxo fields")?;
+    writeln!(writer, "ASCII")?;
+    writeln!(writer, "DATASET STRUCTURED_POINTS")?;
+    writeln!(writer, "DIMENSIONS {} {} 1", width + 1, height + 1)?;
+    writeln!(writer, "ORIGIN 0 0 0")?;
+    writeln!(writer, "SPACING 1 1 1")?;
+    writeln!(writer, "CELL_DATA {}", width as u64 * height as u64)?;
+    for (channel, properties) in universe.channels().iter().enumerate() {
+        let name = array_name(&properties.name);
+        writeln!(writer, "SCALARS {name} double 1")?;
+        writeln!(writer, "LOOKUP_TABLE default")?;
+        for property in cells(universe, channel) {
+            writeln!(writer, "{}", property.value)?;
+        }
+        writeln!(writer, "VECTORS {name}_field double")?;
+        for Property { field, .. } in cells(universe, channel) {
+            writeln!(writer, "{} {} 0", field.x, field.y)?;
+        }
+    }
+    Ok(())
+}
+
+pub fn write_xml_image(writer: &mut impl Write, universe: &Universe) -> io::Result<()> {
+    let extent = format!("0 {} 0 {} 0 0", universe.width, universe.height);
+    writeln!(writer, r#"<?xml version="1.0"?>"#)?;
+    writeln!(
+        writer,
+        r#"<VTKFile type="ImageData" version="1.0" byte_order="LittleEndian">"#
+    )?;
+    writeln!(
+        writer,
+        r#"  <ImageData WholeExtent="{extent}" Origin="0 0 0" Spacing="1 1 1">"#
+    )?;
+    writeln!(writer, r#"    <Piece Extent="{extent}">"#)?;
+    match universe.channels().first() {
+        Some(first) => {
+            let name = xml_escape(&first.name);
+            writeln!(
+                writer,
+                r#"      <CellData Scalars="{name}" Vectors="{name}_field">"#
+            )?;
+        }
+        None => writeln!(writer, "      <CellData>")?,
+    }
+    for (channel, properties) in universe.channels().iter().enumerate() {
+        let name = xml_escape(&properties.name);
+        writeln!(
+            writer,
+            r#"        <DataArray type="Float64" Name="{name}" format="ascii">"#
+        )?;
+        for property in cells(universe, channel) {
+            writeln!(writer, "{}", property.value)?;
+        }
+        writeln!(writer, "        </DataArray>")?;
+        writeln!(
+            writer,
+            r#"        <DataArray type="Float64" Name="{name}_field" NumberOfComponents="3" format="ascii">"#
+        )?;
+        for Property { field, .. } in cells(universe, channel) {
+            writeln!(writer, "{} {} 0", field.x, field.y)?;
+        }
+        writeln!(writer, "        </DataArray>")?;
+    }
+    writeln!(writer, "      </CellData>")?;
+    writeln!(writer, "    </Piece>")?;
+    writeln!(writer, "  </ImageData>")?;
+    writeln!(writer, "</VTKFile>")
+}
+
+pub fn write_legacy_portals(writer: &mut impl Write, universe: &Universe) -> io::Result<()> {
+    let lines = portal_lines(universe);
+    writeln!(writer, "# vtk DataFile Version 3.0")?;
+    writeln!(writer, "simulador_de_fluxo portals")?;
+    writeln!(writer, "ASCII")?;
+    writeln!(writer, "DATASET POLYDATA")?;
+    writeln!(writer, "POINTS {} double", lines.len() * 2)?;
+    for (_, a, b) in &lines {
+        writeln!(writer, "{} {} 0 {} {} 0", a.x, a.y, b.x, b.y)?;
+    }
+    writeln!(writer, "LINES {} {}", lines.len(), lines.len() * 3)?;
+    for i in 0..lines.len() {
+        writeln!(writer, "2 {} {}", 2 * i, 2 * i + 1)?;
+    }
+    writeln!(writer, "CELL_DATA {}", lines.len())?;
+    writeln!(writer, "SCALARS set int 1")?;
+    writeln!(writer, "LOOKUP_TABLE default")?;
+    for (set, _, _) in &lines {
+        writeln!(writer, "{set}")?;
+    }
+    Ok(())
+}
+
+pub fn write_xml_portals(writer: &mut impl Write, universe: &Universe) -> io::Result<()> {
+    let lines = portal_lines(universe);
+    writeln!(writer, r#"<?xml version="1.0"?>"#)?;
+    writeln!(
+        writer,
+        r#"<VTKFile type="PolyData" version="1.0" byte_order="LittleEndian">"#
+    )?;
+    writeln!(writer, "  <PolyData>")?;
+    writeln!(
+        writer,
+        r#"    <Piece NumberOfPoints="{}" NumberOfLines="{}">"#,
+        lines.len() * 2,
+        lines.len()
+    )?;
+    writeln!(writer, "      <Points>")?;
+    writeln!(
+        writer,
+        r#"        <DataArray type="Float64" NumberOfComponents="3" format="ascii">"#
+    )?;
+    for (_, a, b) in &lines {
+        writeln!(writer, "{} {} 0 {} {} 0", a.x, a.y, b.x, b.y)?;
+    }
+    writeln!(writer, "        </DataArray>")?;
+    writeln!(writer, "      </Points>")?;
+    writeln!(writer, "      <Lines>")?;
+    writeln!(
+        writer,
+        r#"        <DataArray type="Int64" Name="connectivity" format="ascii">"#
+    )?;
+    for i in 0..lines.len() {
+        writeln!(writer, "{} {}", 2 * i, 2 * i + 1)?;
+    }
+    writeln!(writer, "        </DataArray>")?;
+    writeln!(
+        writer,
+        r#"        <DataArray type="Int64" Name="offsets" format="ascii">"#
+    )?;
+    for i in 1..=lines.len() {
+        writeln!(writer, "{}", 2 * i)?;
+    }
+    writeln!(writer, "        </DataArray>")?;
+    writeln!(writer, "      </Lines>")?;
+    writeln!(writer, r#"      <CellData Scalars="set">"#)?;
+    writeln!(
+        writer,
+        r#"        <DataArray type="Int32" Name="set" format="ascii">"#
+    )?;
+    for (set, _, _) in &lines {
+        writeln!(writer, "{set}")?;
+    }
+    writeln!(writer, "        </DataArray>")?;
+    writeln!(writer, "      </CellData>")?;
+    writeln!(writer, "    </Piece>")?;
+    writeln!(writer, "  </PolyData>")?;
+    writeln!(writer, "</VTKFile>")
+}
+
+fn xml_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{Channel, Portal, PortalSet};
+
+    /// A 3×2 universe of a mass and a channel of an awkward name, with one portal set.
+    fn universe() -> Universe {
+        let channels = vec![Channel::mass(), Channel::new("hot \"air\"", 1.0)];
+        let mut universe = Universe::new(3, 2, channels);
+        let p = |x, y| Point { x, y };
+        universe.add_portal_set(PortalSet::new(
+            Portal::new(p(0.5, 0.5), p(2.5, 0.5)),
+            Portal::new(p(0.5, 1.5), p(2.5, 1.5)),
+        ));
+        universe
+    }
+
+    fn text(write: fn(&mut Vec<u8>, &Universe) -> io::Result<()>) -> String {
+        let mut bytes = Vec::new();
+        write(&mut bytes, &universe()).unwrap();
+        String::from_utf8(bytes).unwrap()
+    }
+
+    /// Rows of numbers after each line of words, split into numbers.
+    fn legacy_sections(text: &str) -> Vec<(&str, Vec<Vec<f64>>)> {
+        let mut sections: Vec<(&str, Vec<Vec<f64>>)> = Vec::new();
+        for line in text.lines() {
+            let numbers: Result<Vec<f64>, _> = line.split(' ').map(str::parse).collect();
+            match (numbers, sections.last_mut()) {
+                (Ok(numbers), Some((_, rows))) => rows.push(numbers),
+                _ => sections.push((line, Vec::new())),
+            }
+        }
+        sections
+    }
+
+    /// Opening tag and rows of numbers of each `DataArray`.
+    fn xml_arrays(text: &str) -> Vec<(&str, Vec<Vec<f64>>)> {
+        let mut arrays = Vec::new();
+        let mut lines = text.lines().map(str::trim);
+        while let Some(line) = lines.next() {
+            if line.starts_with("<DataArray") {
+                let rows = (lines.by_ref())
+                    .take_while(|line| *line != "</DataArray>")
+                    .map(|row| row.split(' ').map(|n| n.parse().unwrap()).collect())
+                    .collect();
+                arrays.push((line, rows));
+            }
+        }
+        arrays
+    }
+
+    fn widths(rows: &[Vec<f64>]) -> Vec<usize> {
+        rows.iter().map(Vec::len).collect()
+    }
+
+    #[test]
+    fn legacy_images_hold_an_array_per_channel() {
+        let text = text(write_legacy_image);
+        assert!(text.contains("\nDIMENSIONS 4 3 1\n"));
+        assert!(text.contains("\nCELL_DATA 6\n"));
+        let sections = legacy_sections(&text);
+        let arrays: Vec<_> = (sections.iter())
+            .filter(|(_, rows)| !rows.is_empty())
+            .map(|(header, rows)| (*header, widths(rows)))
+            .collect();
+        assert_eq!(
+            arrays,
+            [
+                ("LOOKUP_TABLE default", vec![1; 6]),
+                ("VECTORS mass_field double", vec![3; 6]),
+                ("LOOKUP_TABLE default", vec![1; 6]),
+                ("VECTORS hot_\"air\"_field double", vec![3; 6]),
+            ]
+        );
+        assert!(text.contains("\nSCALARS hot_\"air\" double 1\n"));
+    }
+
+    #[test]
+    fn xml_images_hold_an_array_per_channel() {
+        let text = text(write_xml_image);
+        assert!(text.contains(r#"WholeExtent="0 3 0 2 0 0""#));
+        let arrays: Vec<_> = (xml_arrays(&text).into_iter())
+            .map(|(tag, rows)| (tag.to_string(), widths(&rows)))
+            .collect();
+        let array = |name, components| {
+            let vector = match components {
+                1 => "",
+                _ => r#" NumberOfComponents="3""#,
+            };
+            let tag = format!(r#"<DataArray type="Float64" Name="{name}"{vector} format="ascii">"#);
+            (tag, vec![components; 6])
+        };
+        let expected = [
+            array("mass", 1),
+            array("mass_field", 3),
+            array("hot &quot;air&quot;", 1),
+            array("hot &quot;air&quot;_field", 3),
+        ];
+        assert_eq!(arrays, expected);
+        assert!(text.contains(r#"<CellData Scalars="mass" Vectors="mass_field">"#));
+    }
+
+    #[test]
+    fn legacy_portals_are_a_line_each() {
+        let text = text(write_legacy_portals);
+        let sections = legacy_sections(&text);
+        let section = |header| {
+            let (_, rows) = sections.iter().find(|(h, _)| *h == header).unwrap();
+            rows.clone()
+        };
+        // Both ends of a portal on a row.
+        assert_eq!(
+            section("POINTS 4 double"),
+            [
+                vec![0.5, 0.5, 0.0, 2.5, 0.5, 0.0],
+                vec![0.5, 1.5, 0.0, 2.5, 1.5, 0.0]
+            ]
+        );
+        assert_eq!(section("LINES 2 6"), [[2.0, 0.0, 1.0], [2.0, 2.0, 3.0]]);
+        assert_eq!(section("LOOKUP_TABLE default"), [[0.0], [0.0]]);
+        assert!(text.contains("\nCELL_DATA 2\n"));
+    }
+
+    #[test]
+    fn xml_portals_are_a_line_each() {
+        let text = text(write_xml_portals);
+        assert!(text.contains(r#"<Piece NumberOfPoints="4" NumberOfLines="2">"#));
+        let arrays = xml_arrays(&text);
+        let array = |name: &str| {
+            let (_, rows) = (arrays.iter())
+                .find(|(tag, _)| tag.contains(&format!(r#"Name="{name}""#)))
+                .unwrap();
+            rows.clone()
+        };
+        let (_, points) = &arrays[0];
+        assert_eq!(widths(points), [6, 6]);
+        assert_eq!(array("connectivity"), [[0.0, 1.0], [2.0, 3.0]]);
+        assert_eq!(array("offsets"), [[2.0], [4.0]]);
+        assert_eq!(array("set"), [[0.0], [0.0]]);
+    }
+
+    #[test]
+    fn names_are_escaped() {
+        assert_eq!(array_name("hot air\tflow"), "hot_air_flow");
+        assert_eq!(
+            xml_escape(r#"<"a" & 'b'>"#),
+            r#"&lt;&quot;a&quot; &amp; 'b'&gt;"#
+        );
+    }
+}